@@ -0,0 +1,305 @@
+use crate::parser::Command;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Rotate the log once it grows past this size
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+/// Number of rotated logs to keep (audit.jsonl.1 .. audit.jsonl.N)
+const MAX_ROTATED_LOGS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Allow,
+    Deny,
+    Load,
+    Refuse,
+    Unload,
+}
+
+/// One line of the audit log
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEvent {
+    /// Unix timestamp of the event
+    pub timestamp: u64,
+    pub event: EventKind,
+    /// Directory of the .local_environment file
+    pub dir: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn new(event: EventKind, dir: &Path) -> Self {
+        Self {
+            timestamp: now(),
+            event,
+            dir: dir.to_path_buf(),
+            file_hash: None,
+            commands: Vec::new(),
+            reason: None,
+        }
+    }
+
+    pub fn with_hash(mut self, file_hash: String) -> Self {
+        self.file_hash = Some(file_hash);
+        self
+    }
+
+    pub fn with_commands(mut self, commands: &[Command]) -> Self {
+        self.commands = commands.iter().map(Command::to_string).collect();
+        self
+    }
+
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    /// Append the event to the audit log, warning instead of failing so that
+    /// a full disk never stops an environment from loading
+    pub fn record(self) {
+        if let Err(e) = AuditLog::append(&self) {
+            eprintln!("durrrrrenv: Warning: failed to write audit log: {:#}", e);
+        }
+    }
+
+    /// One-line human readable form
+    pub fn describe(&self) -> String {
+        let mut line = format!(
+            "{}  {:<6}  {}",
+            format_timestamp(self.timestamp),
+            format!("{:?}", self.event).to_lowercase(),
+            self.dir.display()
+        );
+
+        if let Some(hash) = &self.file_hash {
+            line.push_str(&format!("  [{}]", &hash[..hash.len().min(12)]));
+        }
+        if let Some(reason) = &self.reason {
+            line.push_str(&format!("  ({})", reason));
+        }
+        for command in &self.commands {
+            line.push_str(&format!("\n    {}", command));
+        }
+
+        line
+    }
+}
+
+pub struct AuditLog;
+
+impl AuditLog {
+    /// Get the path to the audit log
+    pub fn log_path() -> Result<PathBuf> {
+        #[cfg(test)]
+        let state_dir = crate::paths::test_dir().join("state");
+        #[cfg(not(test))]
+        let state_dir = dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .context("Failed to determine state directory")?
+            .join("durrrrrenv");
+
+        fs::create_dir_all(&state_dir)
+            .context("Failed to create state directory")?;
+
+        Ok(state_dir.join("audit.jsonl"))
+    }
+
+    fn append(event: &AuditEvent) -> Result<()> {
+        let path = Self::log_path()?;
+
+        if fs::metadata(&path).map(|m| m.len() > MAX_LOG_SIZE).unwrap_or(false) {
+            Self::rotate(&path)?;
+        }
+
+        let mut line = serde_json::to_string(event).context("Failed to serialize audit event")?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(0o600)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        file.write_all(line.as_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        Ok(())
+    }
+
+    /// audit.jsonl -> audit.jsonl.1 -> audit.jsonl.2 ..., dropping the oldest
+    fn rotate(path: &Path) -> Result<()> {
+        for n in (1..MAX_ROTATED_LOGS).rev() {
+            let from = Self::rotated_path(path, n);
+            if from.exists() {
+                fs::rename(&from, Self::rotated_path(path, n + 1))?;
+            }
+        }
+        fs::rename(path, Self::rotated_path(path, 1))?;
+        Ok(())
+    }
+
+    fn rotated_path(path: &Path, n: usize) -> PathBuf {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    /// Read all events, oldest first, including rotated logs
+    pub fn read_all() -> Result<Vec<AuditEvent>> {
+        let path = Self::log_path()?;
+        let mut files: Vec<PathBuf> = (1..=MAX_ROTATED_LOGS)
+            .rev()
+            .map(|n| Self::rotated_path(&path, n))
+            .collect();
+        files.push(path);
+
+        let mut events = Vec::new();
+        for file in files {
+            let Ok(contents) = fs::read_to_string(&file) else {
+                continue;
+            };
+            // Skip lines that don't parse (e.g. a write cut short) rather than failing
+            events.extend(
+                contents
+                    .lines()
+                    .filter_map(|line| serde_json::from_str::<AuditEvent>(line).ok()),
+            );
+        }
+
+        Ok(events)
+    }
+}
+
+/// Current Unix time in seconds
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Parse a point in time: a relative age like `30m`, `12h`, `2d` or `1w`,
+/// a local date `YYYY-MM-DD`, a local date and time `YYYY-MM-DD HH:MM`,
+/// or a Unix timestamp
+pub fn parse_time(value: &str, now: u64) -> Result<u64> {
+    let value = value.trim();
+
+    if let Some(unit) = value.chars().last().filter(|c| c.is_ascii_alphabetic()) {
+        let amount: u64 = value[..value.len() - 1]
+            .parse()
+            .with_context(|| format!("Invalid time '{}'", value))?;
+        let seconds = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => anyhow::bail!("Invalid time unit in '{}', expected s, m, h, d or w", value),
+        };
+        let age = amount
+            .checked_mul(seconds)
+            .with_context(|| format!("Time '{}' is too far in the past", value))?;
+        return Ok(now.saturating_sub(age));
+    }
+
+    if let Ok(timestamp) = value.parse::<u64>() {
+        return Ok(timestamp);
+    }
+
+    let (date, time) = value
+        .split_once([' ', 'T'])
+        .unwrap_or((value, "00:00"));
+    let date: Vec<i32> = date.split('-').map(str::parse).collect::<Result<_, _>>()
+        .with_context(|| format!("Invalid date '{}'", value))?;
+    let time: Vec<i32> = time.split(':').map(str::parse).collect::<Result<_, _>>()
+        .with_context(|| format!("Invalid time '{}'", value))?;
+
+    if date.len() != 3 || !(2..=3).contains(&time.len()) {
+        anyhow::bail!("Invalid time '{}', expected YYYY-MM-DD [HH:MM[:SS]]", value);
+    }
+
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_year = date[0] - 1900;
+    tm.tm_mon = date[1] - 1;
+    tm.tm_mday = date[2];
+    tm.tm_hour = time[0];
+    tm.tm_min = time[1];
+    tm.tm_sec = time.get(2).copied().unwrap_or(0);
+    tm.tm_isdst = -1;
+
+    let timestamp = unsafe { libc::mktime(&mut tm) };
+    if timestamp < 0 {
+        anyhow::bail!("Invalid time '{}'", value);
+    }
+
+    Ok(timestamp as u64)
+}
+
+/// Format a Unix timestamp as local `YYYY-MM-DD HH:MM:SS`
+pub fn format_timestamp(timestamp: u64) -> String {
+    let time = timestamp as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return timestamp.to_string();
+    }
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_relative_time() {
+        assert_eq!(parse_time("30m", 10_000).unwrap(), 10_000 - 1800);
+        assert_eq!(parse_time("1d", 100_000).unwrap(), 100_000 - 86_400);
+        assert_eq!(parse_time("2w", 0).unwrap(), 0);
+        assert!(parse_time("3y", 0).is_err());
+        assert!(parse_time("99999999999999999w", 0).is_err());
+    }
+
+    #[test]
+    fn test_parse_absolute_time() {
+        assert_eq!(parse_time("1700000000", 0).unwrap(), 1_700_000_000);
+
+        let date = parse_time("2024-03-01", 0).unwrap();
+        let date_time = parse_time("2024-03-01 12:30", 0).unwrap();
+        assert_eq!(date_time - date, 12 * 3600 + 30 * 60);
+        assert_eq!(format_timestamp(date_time), "2024-03-01 12:30:00");
+
+        assert!(parse_time("2024-03", 0).is_err());
+    }
+
+    #[test]
+    fn test_event_roundtrip() {
+        let event = AuditEvent::new(EventKind::Refuse, Path::new("/srv/app"))
+            .with_reason("not allowed");
+        let line = serde_json::to_string(&event).unwrap();
+        assert!(line.contains(r#""event":"refuse""#));
+
+        let parsed: AuditEvent = serde_json::from_str(&line).unwrap();
+        assert_eq!(parsed.event, EventKind::Refuse);
+        assert_eq!(parsed.reason.as_deref(), Some("not allowed"));
+    }
+}
@@ -0,0 +1,254 @@
+use crate::executor::Executor;
+use anyhow::Result;
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Everything needed to activate and deactivate a conda environment
+#[derive(Debug)]
+pub struct CondaEnv {
+    /// Environment name, or its path when it lives outside the envs directories
+    pub name: String,
+    pub prefix: PathBuf,
+    /// etc/conda/activate.d/*.sh, in the order conda runs them
+    pub activate_scripts: Vec<PathBuf>,
+    /// etc/conda/deactivate.d/*.sh, in the order conda runs them
+    pub deactivate_scripts: Vec<PathBuf>,
+    /// Variables set with `conda env config vars set`
+    pub env_vars: Vec<(String, String)>,
+}
+
+pub struct Conda;
+
+impl Conda {
+    /// Find the environment for `conda_env <name|path>`
+    pub fn resolve(spec: &str, working_dir: &Path) -> Result<CondaEnv> {
+        let (name, prefix) = if spec.contains('/') {
+            let prefix = Executor::resolve_path(spec, working_dir)?;
+            (prefix.display().to_string(), prefix)
+        } else {
+            (spec.to_string(), Self::find_by_name(spec)?)
+        };
+
+        if !prefix.join("conda-meta").is_dir() {
+            anyhow::bail!("{} is not a conda environment (no conda-meta directory)", prefix.display());
+        }
+
+        let mut deactivate_scripts = Self::scripts(&prefix.join("etc/conda/deactivate.d"));
+        deactivate_scripts.reverse();
+
+        Ok(CondaEnv {
+            name,
+            activate_scripts: Self::scripts(&prefix.join("etc/conda/activate.d")),
+            deactivate_scripts,
+            env_vars: Self::env_vars(&prefix),
+            prefix,
+        })
+    }
+
+    /// Look a named environment up in the envs directories; `base` is the install root
+    fn find_by_name(name: &str) -> Result<PathBuf> {
+        let roots = Self::roots();
+
+        if name == "base" {
+            return roots
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Cannot find the conda installation for 'base'"));
+        }
+
+        let envs_dirs = Self::envs_dirs(&roots);
+        if let Some(prefix) = envs_dirs
+            .iter()
+            .map(|dir| dir.join(name))
+            .find(|prefix| prefix.join("conda-meta").is_dir())
+        {
+            return Ok(prefix);
+        }
+
+        let mut available: Vec<String> = envs_dirs
+            .iter()
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().join("conda-meta").is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        available.sort();
+        available.dedup();
+
+        anyhow::bail!(
+            "conda environment '{}' not found in {}. Available: {}",
+            name,
+            envs_dirs
+                .iter()
+                .map(|dir| dir.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            if available.is_empty() { "none".to_string() } else { available.join(", ") }
+        )
+    }
+
+    /// Conda and mamba installation roots, the active one first
+    fn roots() -> Vec<PathBuf> {
+        let home = dirs::home_dir().unwrap_or_default();
+        let mut candidates = Vec::new();
+
+        // $CONDA_EXE is <root>/bin/conda
+        if let Some(exe) = env::var_os("CONDA_EXE") {
+            if let Some(root) = Path::new(&exe).parent().and_then(Path::parent) {
+                candidates.push(root.to_path_buf());
+            }
+        }
+        for var in ["MAMBA_ROOT_PREFIX", "CONDA_ROOT"] {
+            if let Some(root) = env::var_os(var) {
+                candidates.push(PathBuf::from(root));
+            }
+        }
+        for name in ["miniforge3", "mambaforge", "miniconda3", "anaconda3", "micromamba"] {
+            candidates.push(home.join(name));
+        }
+        candidates.push(PathBuf::from("/opt/conda"));
+
+        let mut roots: Vec<PathBuf> = Vec::new();
+        for root in candidates {
+            if root.join("conda-meta").is_dir() && !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
+        roots
+    }
+
+    /// Directories holding named environments: envs_dirs from .condarc,
+    /// $CONDA_ENVS_PATH, each root's envs and ~/.conda/envs
+    fn envs_dirs(roots: &[PathBuf]) -> Vec<PathBuf> {
+        let home = dirs::home_dir().unwrap_or_default();
+        let condarc = env::var_os("CONDARC")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home.join(".condarc"));
+
+        let mut dirs: Vec<PathBuf> = fs::read_to_string(&condarc)
+            .map(|contents| Self::parse_envs_dirs(&contents))
+            .unwrap_or_default()
+            .iter()
+            .map(|dir| Executor::resolve_path(dir, &home).unwrap_or_else(|_| PathBuf::from(dir)))
+            .collect();
+
+        if let Some(paths) = env::var_os("CONDA_ENVS_PATH") {
+            dirs.extend(env::split_paths(&paths));
+        }
+        dirs.extend(roots.iter().map(|root| root.join("envs")));
+        dirs.push(home.join(".conda/envs"));
+        dirs
+    }
+
+    /// The `envs_dirs` list from .condarc, as a block or flow sequence
+    fn parse_envs_dirs(condarc: &str) -> Vec<String> {
+        let unquote = |item: &str| item.trim().trim_matches(['"', '\'']).to_string();
+        let mut lines = condarc.lines();
+        let mut dirs = Vec::new();
+
+        while let Some(line) = lines.next() {
+            let Some(value) = line.strip_prefix("envs_dirs:") else {
+                continue;
+            };
+
+            let value = value.trim();
+            if let Some(flow) = value.strip_prefix('[') {
+                return flow
+                    .trim_end_matches(']')
+                    .split(',')
+                    .map(unquote)
+                    .filter(|item| !item.is_empty())
+                    .collect();
+            }
+
+            for item in lines.by_ref() {
+                let trimmed = item.trim();
+                if trimmed.is_empty() || trimmed.starts_with('#') {
+                    continue;
+                }
+                match trimmed.strip_prefix("- ") {
+                    Some(dir) if item.starts_with(char::is_whitespace) || item.starts_with('-') => {
+                        dirs.push(unquote(dir))
+                    }
+                    _ => break,
+                }
+            }
+            break;
+        }
+
+        dirs
+    }
+
+    /// Shell scripts in an activate.d or deactivate.d directory, sorted by name
+    fn scripts(dir: &Path) -> Vec<PathBuf> {
+        let mut scripts: Vec<PathBuf> = fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "sh"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        scripts.sort();
+        scripts
+    }
+
+    /// Variables from `conda env config vars`, stored in conda-meta/state
+    fn env_vars(prefix: &Path) -> Vec<(String, String)> {
+        let Ok(contents) = fs::read_to_string(prefix.join("conda-meta/state")) else {
+            return Vec::new();
+        };
+        let Ok(state) = serde_json::from_str::<Value>(&contents) else {
+            return Vec::new();
+        };
+
+        let mut vars: Vec<(String, String)> = state["env_vars"]
+            .as_object()
+            .map(|vars| {
+                vars.iter()
+                    .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default();
+        vars.sort();
+        vars
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_envs_dirs() {
+        let block = "channels:\n  - conda-forge\nenvs_dirs:\n  - ~/conda-envs\n  - \"/opt/shared envs\"\nchannel_priority: strict\n";
+        assert_eq!(Conda::parse_envs_dirs(block), vec!["~/conda-envs", "/opt/shared envs"]);
+
+        let flow = "envs_dirs: [~/envs, /data/envs]\n";
+        assert_eq!(Conda::parse_envs_dirs(flow), vec!["~/envs", "/data/envs"]);
+    }
+
+    #[test]
+    fn test_resolve_env_by_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let prefix = dir.path().join("env");
+        fs::create_dir_all(prefix.join("conda-meta")).unwrap();
+        fs::create_dir_all(prefix.join("etc/conda/activate.d")).unwrap();
+        fs::create_dir_all(prefix.join("etc/conda/deactivate.d")).unwrap();
+        for name in ["b.sh", "a.sh", "notes.txt"] {
+            fs::write(prefix.join("etc/conda/activate.d").join(name), "").unwrap();
+            fs::write(prefix.join("etc/conda/deactivate.d").join(name), "").unwrap();
+        }
+        fs::write(prefix.join("conda-meta/state"), r#"{"env_vars": {"SPARK_HOME": "/opt/spark"}}"#).unwrap();
+
+        let env = Conda::resolve("./env", dir.path()).unwrap();
+        assert_eq!(env.activate_scripts.last().unwrap().file_name().unwrap(), "b.sh");
+        assert_eq!(env.deactivate_scripts.first().unwrap().file_name().unwrap(), "b.sh");
+        assert_eq!(env.env_vars, vec![("SPARK_HOME".to_string(), "/opt/spark".to_string())]);
+
+        assert!(Conda::resolve("./missing", dir.path()).is_err());
+    }
+}
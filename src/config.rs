@@ -1,14 +1,32 @@
+use crate::git::Repository;
+use crate::paths;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+/// Current layout version of allowed.json
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Upgrades from one layout version to the next; entry N migrates version N to N + 1
+const MIGRATIONS: &[fn(&mut Value) -> Result<()>] = &[migrate_v0_to_v1, migrate_v1_to_v2, migrate_v2_to_v3];
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
+    /// Layout version this file was written with
+    pub version: u32,
     /// Map of directory hash -> allowed status and metadata
     pub allowed_dirs: HashMap<String, DirInfo>,
+    /// Map of repository identity + relative path hash -> repository-wide trust
+    pub repo_trust: HashMap<String, RepoTrustInfo>,
+    /// Map of directory hash -> file content the user chose never to load
+    pub denied: HashMap<String, DeniedInfo>,
+    /// Fields written by newer versions, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,11 +37,102 @@ pub struct DirInfo {
     pub file_hash: String,
     /// Timestamp when it was allowed
     pub allowed_at: u64,
+    /// Programs of process substitutions, resolved when the file was allowed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pinned_binaries: Vec<PinnedBinary>,
+    /// Directives the user rejected when allowing, as written in the file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_directives: Vec<String>,
+    /// Fields written by newer versions, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PinnedBinary {
+    /// Program name as written in the command
+    pub program: String,
+    /// Absolute path it resolved to, or None if it was not found
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Hash of the binary, if hashing was enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoTrustInfo {
+    /// Repository identity (origin URL or root commit)
+    pub identity: String,
+    /// Directory of the .local_environment file relative to the repository root
+    pub relative_path: PathBuf,
+    /// Hash of the .local_environment file content when it was allowed
+    pub file_hash: String,
+    /// Timestamp when it was allowed
+    pub allowed_at: u64,
+    /// Programs of process substitutions, resolved when the file was allowed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pinned_binaries: Vec<PinnedBinary>,
+    /// Directives the user rejected when allowing, as written in the file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_directives: Vec<String>,
+    /// Fields written by newer versions, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeniedInfo {
+    /// Full canonical path to the directory
+    pub path: PathBuf,
+    /// Hash of the .local_environment file content that was denied
+    pub file_hash: String,
+    /// Timestamp when it was denied
+    pub denied_at: u64,
+    /// Fields written by newer versions, kept so saving doesn't drop them
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            allowed_dirs: HashMap::new(),
+            repo_trust: HashMap::new(),
+            denied: HashMap::new(),
+            extra: Map::new(),
+        }
+    }
+}
+
+/// Version 0 had no version field; the layout is otherwise unchanged
+fn migrate_v0_to_v1(value: &mut Value) -> Result<()> {
+    let object = value.as_object_mut().context("Config is not a JSON object")?;
+    object.entry("allowed_dirs").or_insert_with(|| Value::Object(Map::new()));
+    Ok(())
+}
+
+/// Version 2 added repository-scoped trust
+fn migrate_v1_to_v2(value: &mut Value) -> Result<()> {
+    let object = value.as_object_mut().context("Config is not a JSON object")?;
+    object.entry("repo_trust").or_insert_with(|| Value::Object(Map::new()));
+    Ok(())
+}
+
+/// Version 3 added persistent denials
+fn migrate_v2_to_v3(value: &mut Value) -> Result<()> {
+    let object = value.as_object_mut().context("Config is not a JSON object")?;
+    object.entry("denied").or_insert_with(|| Value::Object(Map::new()));
+    Ok(())
 }
 
 impl Config {
     /// Get the path to the config file
     pub fn config_path() -> Result<PathBuf> {
+        #[cfg(test)]
+        let config_dir = paths::test_dir().join("durrrrrenv");
+        #[cfg(not(test))]
         let config_dir = dirs::config_dir()
             .context("Failed to determine config directory")?
             .join("durrrrrenv");
@@ -45,20 +154,41 @@ impl Config {
         let contents = fs::read_to_string(&config_path)
             .context("Failed to read config file")?;
 
-        let config: Config = serde_json::from_str(&contents)
-            .context("Failed to parse config file")?;
+        Self::from_json(&contents).context("Failed to parse config file")
+    }
 
-        Ok(config)
+    /// Parse config text, migrating older layouts to the current one
+    pub fn from_json(contents: &str) -> Result<Self> {
+        let mut value: Value = serde_json::from_str(contents)?;
+
+        let mut version = value
+            .get("version")
+            .and_then(Value::as_u64)
+            .unwrap_or(0) as u32;
+
+        while (version as usize) < MIGRATIONS.len() {
+            MIGRATIONS[version as usize](&mut value)
+                .with_context(|| format!("Failed to migrate config from version {}", version))?;
+            version += 1;
+            value["version"] = Value::from(version);
+        }
+
+        // Files from newer versions are read as-is; unknown fields end up in `extra`
+        Ok(serde_json::from_value(value)?)
     }
 
-    /// Save config to disk
+    /// Save config to disk, through a temporary file so that a crash can't
+    /// leave a truncated trust database behind
     pub fn save(&self) -> Result<()> {
         let config_path = Self::config_path()?;
         let contents = serde_json::to_string_pretty(self)
             .context("Failed to serialize config")?;
 
-        fs::write(&config_path, contents)
+        let temp_path = config_path.with_extension(format!("json.{}.tmp", std::process::id()));
+        fs::write(&temp_path, contents)
             .context("Failed to write config file")?;
+        fs::rename(&temp_path, &config_path)
+            .context("Failed to replace config file")?;
 
         Ok(())
     }
@@ -75,10 +205,30 @@ impl Config {
         false
     }
 
+    /// Get the trust entry for a directory
+    pub fn dir_info(&self, dir: &Path) -> Option<&DirInfo> {
+        self.allowed_dirs.get(&Self::hash_path(dir))
+    }
+
     /// Add a directory to the allowed list
-    pub fn allow(&mut self, dir: &Path, file_content: &str) -> Result<()> {
+    pub fn allow(
+        &mut self,
+        dir: &Path,
+        file_content: &str,
+        pins: Vec<PinnedBinary>,
+        skipped: Vec<String>,
+    ) -> Result<()> {
+        let info = self.trust_hash(dir, Self::hash_content(file_content))?;
+        info.pinned_binaries = pins;
+        info.skipped_directives = skipped;
+        self.save()?;
+
+        Ok(())
+    }
+
+    /// Record a directory as allowed for a known file hash without saving
+    pub fn trust_hash(&mut self, dir: &Path, file_hash: String) -> Result<&mut DirInfo> {
         let dir_key = Self::hash_path(dir);
-        let file_hash = Self::hash_content(file_content);
         let canonical_path = fs::canonicalize(dir)
             .context("Failed to canonicalize directory path")?;
 
@@ -89,35 +239,499 @@ impl Config {
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap()
                 .as_secs(),
+            pinned_binaries: Vec::new(),
+            skipped_directives: Vec::new(),
+            extra: Map::new(),
+        };
+
+        self.denied.remove(&dir_key);
+        self.allowed_dirs.insert(dir_key.clone(), info);
+
+        Ok(self.allowed_dirs.get_mut(&dir_key).unwrap())
+    }
+
+    /// Check if an env file is trusted for every checkout of its repository
+    pub fn is_allowed_in_repo(&self, repo: &Repository, dir: &Path, file_content: &str) -> bool {
+        let Some(relative_path) = repo.relative_path(dir) else {
+            return false;
+        };
+
+        match self.repo_trust.get(&Self::repo_key(&repo.identity, &relative_path)) {
+            Some(info) => info.file_hash == Self::hash_content(file_content),
+            None => false,
+        }
+    }
+
+    /// Get the repository-wide trust entry covering a directory
+    pub fn repo_info(&self, repo: &Repository, dir: &Path) -> Option<&RepoTrustInfo> {
+        let relative_path = repo.relative_path(dir)?;
+        self.repo_trust.get(&Self::repo_key(&repo.identity, &relative_path))
+    }
+
+    /// Trust an env file in every worktree and clone of its repository
+    pub fn allow_in_repo(
+        &mut self,
+        repo: &Repository,
+        dir: &Path,
+        file_content: &str,
+        pins: Vec<PinnedBinary>,
+        skipped: Vec<String>,
+    ) -> Result<()> {
+        let relative_path = repo
+            .relative_path(dir)
+            .with_context(|| format!("{} is not inside {}", dir.display(), repo.root.display()))?;
+
+        let info = RepoTrustInfo {
+            identity: repo.identity.clone(),
+            relative_path: relative_path.clone(),
+            file_hash: Self::hash_content(file_content),
+            allowed_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            pinned_binaries: pins,
+            skipped_directives: skipped,
+            extra: Map::new(),
         };
 
-        self.allowed_dirs.insert(dir_key, info);
+        self.denied.remove(&Self::hash_path(dir));
+        self.repo_trust
+            .insert(Self::repo_key(&repo.identity, &relative_path), info);
         self.save()?;
 
         Ok(())
     }
 
+    /// Replace the pinned binaries of the entries covering a directory, e.g.
+    /// once a venv the programs resolve to has been created
+    pub fn update_pins(&mut self, dir: &Path, pins: Vec<PinnedBinary>) -> Result<()> {
+        if let Some(info) = self.allowed_dirs.get_mut(&Self::hash_path(dir)) {
+            info.pinned_binaries = pins.clone();
+        }
+
+        if let Some(repo) = Repository::discover(dir) {
+            if let Some(relative_path) = repo.relative_path(dir) {
+                if let Some(info) = self.repo_trust.get_mut(&Self::repo_key(&repo.identity, &relative_path)) {
+                    info.pinned_binaries = pins;
+                }
+            }
+        }
+
+        self.save()
+    }
+
     /// Remove a directory from the allowed list
     pub fn deny(&mut self, dir: &Path) -> Result<()> {
         let dir_key = Self::hash_path(dir);
         self.allowed_dirs.remove(&dir_key);
+
+        // Also drop repository-wide trust covering this directory
+        if let Some(repo) = Repository::discover(dir) {
+            if let Some(relative_path) = repo.relative_path(dir) {
+                self.repo_trust
+                    .remove(&Self::repo_key(&repo.identity, &relative_path));
+            }
+        }
+
+        self.save()?;
+
+        Ok(())
+    }
+
+    /// Check if the user chose never to load this exact file content
+    pub fn is_denied(&self, dir: &Path, file_content: &str) -> bool {
+        match self.denied.get(&Self::hash_path(dir)) {
+            Some(info) => info.file_hash == Self::hash_content(file_content),
+            None => false,
+        }
+    }
+
+    /// Stop offering to load a file until its content changes or it is allowed
+    pub fn deny_persistently(&mut self, dir: &Path, file_content: &str) -> Result<()> {
+        let canonical_path = fs::canonicalize(dir)
+            .context("Failed to canonicalize directory path")?;
+
+        let info = DeniedInfo {
+            path: canonical_path,
+            file_hash: Self::hash_content(file_content),
+            denied_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            extra: Map::new(),
+        };
+
+        self.allowed_dirs.remove(&Self::hash_path(dir));
+        self.denied.insert(Self::hash_path(dir), info);
+        self.save()?;
+
+        Ok(())
+    }
+
+    /// Keep a copy of approved file content so later versions can be diffed against it
+    pub fn save_snapshot(file_content: &str) -> Result<()> {
+        let dir = Self::snapshot_dir()?;
+        fs::create_dir_all(&dir)
+            .context("Failed to create snapshot directory")?;
+
+        let path = dir.join(Self::hash_content(file_content));
+        fs::write(&path, file_content)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        Ok(())
+    }
+
+    /// Approved file content for a hash, if a snapshot was kept
+    pub fn load_snapshot(file_hash: &str) -> Option<String> {
+        if !is_sha256_hex(file_hash) {
+            return None;
+        }
+        fs::read_to_string(Self::snapshot_dir().ok()?.join(file_hash)).ok()
+    }
+
+    fn snapshot_dir() -> Result<PathBuf> {
+        Ok(Self::config_path()?.with_file_name("snapshots"))
+    }
+
+    /// Move the trust of one directory over to another, keeping the approved file hash
+    pub fn rekey(&mut self, from: &Path, to: &Path) -> Result<()> {
+        let from_key = Self::hash_path(from);
+        let key = if self.allowed_dirs.contains_key(&from_key) {
+            from_key
+        } else {
+            // The old directory may no longer resolve to its canonical form
+            self.allowed_dirs
+                .iter()
+                .find(|(_, info)| info.path == from)
+                .map(|(key, _)| key.clone())
+                .with_context(|| format!("{} is not allowed", from.display()))?
+        };
+
+        let to_key = Self::hash_path(to);
+        if to_key != key && self.allowed_dirs.contains_key(&to_key) {
+            anyhow::bail!("{} is already allowed; run 'durrrrrenv deny' there first", to.display());
+        }
+        if self.denied.contains_key(&to_key) {
+            anyhow::bail!(
+                "The .local_environment in {} was denied; run 'durrrrrenv allow' there to review it",
+                to.display()
+            );
+        }
+
+        let mut info = self.allowed_dirs.remove(&key).unwrap();
+        info.path = fs::canonicalize(to)
+            .context("Failed to canonicalize directory path")?;
+
+        self.allowed_dirs.insert(to_key, info);
         self.save()?;
 
         Ok(())
     }
 
+    /// Find a trusted entry for the same file content whose directory no longer exists
+    pub fn find_moved(&self, file_content: &str) -> Option<&DirInfo> {
+        let file_hash = Self::hash_content(file_content);
+
+        self.allowed_dirs
+            .values()
+            .filter(|info| info.file_hash == file_hash && !info.path.exists())
+            .max_by_key(|info| info.allowed_at)
+    }
+
+    /// Check the store for inconsistencies, returning a description of each problem
+    pub fn verify(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.version > SCHEMA_VERSION {
+            problems.push(format!(
+                "Config version {} is newer than this durrrrrenv supports ({})",
+                self.version, SCHEMA_VERSION
+            ));
+        }
+
+        let mut keys: Vec<&String> = self.allowed_dirs.keys().collect();
+        keys.sort();
+
+        for key in keys {
+            let info = &self.allowed_dirs[key];
+
+            if !is_sha256_hex(key) {
+                problems.push(format!("Key {} is not a SHA-256 hash", key));
+            }
+            if !info.path.is_absolute() {
+                problems.push(format!("{}: path {} is not absolute", key, info.path.display()));
+            }
+            if Self::hash_key(&info.path) != *key {
+                problems.push(format!(
+                    "{}: key does not match the hash of path {}",
+                    key,
+                    info.path.display()
+                ));
+            }
+            if !is_sha256_hex(&info.file_hash) {
+                problems.push(format!(
+                    "{}: file hash '{}' is not a SHA-256 hash",
+                    key, info.file_hash
+                ));
+            }
+        }
+
+        let mut repo_keys: Vec<&String> = self.repo_trust.keys().collect();
+        repo_keys.sort();
+
+        for key in repo_keys {
+            let info = &self.repo_trust[key];
+
+            if Self::repo_key(&info.identity, &info.relative_path) != *key {
+                problems.push(format!(
+                    "{}: key does not match the hash of {} in {}",
+                    key,
+                    info.relative_path.display(),
+                    info.identity
+                ));
+            }
+            if !is_sha256_hex(&info.file_hash) {
+                problems.push(format!(
+                    "{}: file hash '{}' is not a SHA-256 hash",
+                    key, info.file_hash
+                ));
+            }
+        }
+
+        let mut denied_keys: Vec<&String> = self.denied.keys().collect();
+        denied_keys.sort();
+
+        for key in denied_keys {
+            let info = &self.denied[key];
+
+            if Self::hash_key(&info.path) != *key {
+                problems.push(format!(
+                    "{}: key does not match the hash of denied path {}",
+                    key,
+                    info.path.display()
+                ));
+            }
+            if !is_sha256_hex(&info.file_hash) {
+                problems.push(format!(
+                    "{}: file hash '{}' is not a SHA-256 hash",
+                    key, info.file_hash
+                ));
+            }
+        }
+
+        problems
+    }
+
+    /// Hash a repository identity and relative directory for use as a key
+    fn repo_key(identity: &str, relative_path: &Path) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(identity.as_bytes());
+        hasher.update([0]);
+        hasher.update(relative_path.to_string_lossy().as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
     /// Hash a directory path for use as a key
     fn hash_path(path: &Path) -> String {
-        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        Self::hash_key(&paths::canonical(path))
+    }
+
+    /// Hash an already canonical path
+    fn hash_key(canonical: &Path) -> String {
         let mut hasher = Sha256::new();
         hasher.update(canonical.to_string_lossy().as_bytes());
         format!("{:x}", hasher.finalize())
     }
 
     /// Hash file content
-    fn hash_content(content: &str) -> String {
+    pub fn hash_content(content: &str) -> String {
         let mut hasher = Sha256::new();
         hasher.update(content.as_bytes());
         format!("{:x}", hasher.finalize())
     }
 }
+
+/// Check that a string is a lowercase hex SHA-256 digest
+fn is_sha256_hex(value: &str) -> bool {
+    value.len() == 64 && value.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+
+    const HASH: &str = "954b0caa5aae7f51c5b69f0a02246b4fceac1aac076b3457020f3caa9834c9ea";
+
+    #[test]
+    fn test_migrates_unversioned_config() {
+        let json = format!(
+            r#"{{"allowed_dirs": {{"{}": {{"path": "/srv/app", "file_hash": "{}", "allowed_at": 1}}}}}}"#,
+            Config::hash_key(Path::new("/srv/app")),
+            HASH
+        );
+
+        let config = Config::from_json(&json).unwrap();
+        assert_eq!(config.version, SCHEMA_VERSION);
+        assert_eq!(config.allowed_dirs.len(), 1);
+        assert!(config.repo_trust.is_empty());
+        assert!(config.denied.is_empty());
+        assert!(config.verify().is_empty());
+    }
+
+    #[test]
+    fn test_keeps_unknown_fields() {
+        let json = format!(
+            r#"{{"version": 99, "future": true, "repo_trust": {{}}, "denied": {{}}, "allowed_dirs": {{"k": {{"path": "/srv/app", "file_hash": "{}", "allowed_at": 1, "scope": "repo"}}}}}}"#,
+            HASH
+        );
+
+        let config = Config::from_json(&json).unwrap();
+        assert_eq!(config.version, 99);
+
+        let saved: Value = serde_json::to_value(&config).unwrap();
+        assert_eq!(saved["future"], Value::Bool(true));
+        assert_eq!(saved["allowed_dirs"]["k"]["scope"], Value::from("repo"));
+    }
+
+    #[test]
+    fn test_verify_reports_problems() {
+        let json = r#"{"version": 3, "repo_trust": {}, "denied": {}, "allowed_dirs": {"bogus": {"path": "relative", "file_hash": "xyz", "allowed_at": 1}}}"#;
+
+        let problems = Config::from_json(json).unwrap().verify();
+        assert_eq!(problems.len(), 4);
+    }
+
+    #[test]
+    fn test_rekey_moves_trust_to_new_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        paths::use_test_dir(dir.path());
+        let old = dir.path().join("old");
+        let new = dir.path().join("new");
+        fs::create_dir(&old).unwrap();
+        fs::create_dir(&new).unwrap();
+
+        let mut config = Config::default();
+        config.allow(&old, "export A=1\n", Vec::new(), Vec::new()).unwrap();
+        fs::remove_dir(&old).unwrap();
+        config.rekey(&old, &new).unwrap();
+
+        assert!(config.dir_info(&old).is_none());
+        assert!(config.is_allowed(&new, "export A=1\n"));
+        assert_eq!(config.dir_info(&new).unwrap().path, fs::canonicalize(&new).unwrap());
+        assert!(Config::load().unwrap().is_allowed(&new, "export A=1\n"));
+        assert!(config.rekey(&old, &new).is_err());
+
+        // Neither an existing entry nor a denial at the target is overwritten
+        let other = dir.path().join("other");
+        fs::create_dir(&other).unwrap();
+        config.allow(&other, "export B=1\n", Vec::new(), Vec::new()).unwrap();
+        fs::remove_dir(&new).unwrap();
+        assert!(config.rekey(&new, &other).is_err());
+        assert!(config.is_allowed(&other, "export B=1\n"));
+
+        config.deny_persistently(&other, "export B=1\n").unwrap();
+        assert!(config.rekey(&new, &other).is_err());
+        assert!(config.is_denied(&other, "export B=1\n"));
+        assert!(fs::read_dir(dir.path().join("durrrrrenv")).unwrap().all(|entry| {
+            !entry.unwrap().file_name().to_string_lossy().ends_with(".tmp")
+        }));
+    }
+
+    #[test]
+    fn test_find_moved_matches_by_content_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let here = dir.path().join("here");
+        fs::create_dir(&here).unwrap();
+
+        let mut config = Config::default();
+        config.trust_hash(&here, Config::hash_content("export A=1\n")).unwrap();
+        let gone = config.trust_hash(dir.path(), Config::hash_content("export A=1\n")).unwrap();
+        gone.path = dir.path().join("gone");
+
+        assert_eq!(config.find_moved("export A=1\n").unwrap().path, dir.path().join("gone"));
+        assert!(config.find_moved("export A=2\n").is_none());
+    }
+
+    #[test]
+    fn test_repo_trust_covers_other_checkouts_until_denied() {
+        let dir = tempfile::tempdir().unwrap();
+        paths::use_test_dir(dir.path());
+        let checkout = |name: &str| {
+            let root = dir.path().join(name);
+            fs::create_dir_all(root.join(".git")).unwrap();
+            fs::create_dir_all(root.join("api")).unwrap();
+            fs::write(root.join(".git/config"), "[remote \"origin\"]\n\turl = https://example.com/org/repo.git\n").unwrap();
+            (Repository::discover(&root.join("api")).unwrap(), root.join("api"))
+        };
+        let (repo, api) = checkout("one");
+        let (clone, clone_api) = checkout("two");
+
+        let mut config = Config::default();
+        config.allow_in_repo(&repo, &api, "export A=1\n", Vec::new(), Vec::new()).unwrap();
+
+        assert!(config.is_allowed_in_repo(&clone, &clone_api, "export A=1\n"));
+        assert!(!config.is_allowed_in_repo(&clone, &clone_api, "export A=2\n"));
+        assert!(!config.is_allowed_in_repo(&clone, &clone_api.parent().unwrap().join("web"), "export A=1\n"));
+        assert!(config.allow_in_repo(&repo, dir.path(), "export A=1\n", Vec::new(), Vec::new()).is_err());
+
+        config.deny(&clone_api).unwrap();
+        assert!(config.repo_trust.is_empty());
+        assert!(!Config::load().unwrap().is_allowed_in_repo(&repo, &api, "export A=1\n"));
+    }
+
+    #[test]
+    fn test_skipped_directives_survive_trust_export_and_import() {
+        let dir = tempfile::tempdir().unwrap();
+        paths::use_test_dir(dir.path());
+        let content = "source env.sh\nsource <(./gen.sh)\nsource <(west completion zsh)\n";
+        fs::write(dir.path().join(".local_environment"), content).unwrap();
+
+        let mut config = Config::default();
+        config
+            .allow(dir.path(), content, Vec::new(), vec!["source <(west completion zsh)".to_string()])
+            .unwrap();
+        let export = crate::trust::TrustExport::from_config(&config);
+
+        let mut imported = Config::default();
+        export.import_into(&mut imported, &[], &Settings::default()).unwrap();
+        let info = imported.dir_info(dir.path()).unwrap();
+        assert_eq!(info.skipped_directives, vec!["source <(west completion zsh)"]);
+
+        // Approved programs are pinned again on import, here as not found
+        let pins: Vec<_> = info.pinned_binaries.iter().map(|pin| (pin.program.as_str(), pin.path.clone())).collect();
+        assert_eq!(pins, vec![("./gen.sh", None)]);
+    }
+
+    #[test]
+    fn test_denial_persists_until_content_changes_or_allowed() {
+        let dir = tempfile::tempdir().unwrap();
+        paths::use_test_dir(dir.path());
+
+        let mut config = Config::default();
+        config.allow(dir.path(), "export A=1\n", Vec::new(), Vec::new()).unwrap();
+        config.deny_persistently(dir.path(), "export A=1\n").unwrap();
+        assert!(config.dir_info(dir.path()).is_none());
+
+        let mut config = Config::load().unwrap();
+        assert!(config.is_denied(dir.path(), "export A=1\n"));
+        assert!(!config.is_denied(dir.path(), "export A=2\n"));
+
+        config.allow(dir.path(), "export A=1\n", Vec::new(), Vec::new()).unwrap();
+        assert!(!Config::load().unwrap().is_denied(dir.path(), "export A=1\n"));
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        paths::use_test_dir(dir.path());
+
+        Config::save_snapshot("export A=1\n").unwrap();
+        assert_eq!(
+            Config::load_snapshot(&Config::hash_content("export A=1\n")).as_deref(),
+            Some("export A=1\n")
+        );
+        assert_eq!(Config::load_snapshot(&Config::hash_content("export A=2\n")), None);
+        assert_eq!(Config::load_snapshot("../allowed.json"), None);
+    }
+}
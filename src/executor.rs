@@ -1,16 +1,75 @@
+use crate::conda::Conda;
+use crate::jdk::Jdk;
+use crate::nix::{DevEnv, Nix, Shell};
+use crate::node::Node;
 use crate::parser::Command;
+use crate::python::Python;
+use crate::ruby::Ruby;
+use crate::rust::Rust;
+use crate::sandbox::Sandbox;
+use crate::settings::Settings;
+use crate::tool_versions::ToolVersions;
 use anyhow::{Context, Result};
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 pub struct Executor;
 
+/// The variables the generated script has set up to some point, on top of
+/// durrrrrenv's own environment. Commands run on the way see what the shell
+/// will see at that point, e.g. a venv's bin directory on PATH.
+#[derive(Debug, Clone)]
+pub struct Environment {
+    pub vars: BTreeMap<String, String>,
+}
+
+impl Environment {
+    pub fn inherited() -> Self {
+        let vars = env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .collect();
+        Self { vars }
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        self.vars.get("PATH").map(String::as_str)
+    }
+
+    fn set(&mut self, name: &str, value: &str) {
+        self.vars.insert(name.to_string(), value.to_string());
+    }
+
+    fn add_path(&mut self, dir: &Path) {
+        let path = match self.path() {
+            Some(path) if !path.is_empty() => format!("{}:{}", dir.display(), path),
+            _ => dir.display().to_string(),
+        };
+        self.set("PATH", &path);
+    }
+
+    /// Record the variable and return the line exporting it
+    fn export(&mut self, name: &str, value: &str) -> String {
+        self.set(name, value);
+        Executor::export(name, value)
+    }
+
+    /// Record the directory and return the line putting it on PATH
+    fn prepend_path(&mut self, dir: &Path) -> String {
+        self.add_path(dir);
+        Executor::prepend_path(dir)
+    }
+}
+
 impl Executor {
     /// Generate shell script from parsed commands
-    pub fn generate_shell_script(commands: &[Command], working_dir: &Path) -> Result<String> {
+    pub fn generate_shell_script(commands: &[Command], working_dir: &Path, settings: &Settings) -> Result<String> {
         let mut script = String::new();
+        let mut environment = Environment::inherited();
 
         for cmd in commands {
-            let line = Self::command_to_shell(cmd, working_dir)?;
+            let line = Self::command_to_shell(cmd, working_dir, settings, &mut environment)?;
             script.push_str(&line);
             script.push('\n');
         }
@@ -18,47 +77,266 @@ impl Executor {
         Ok(script)
     }
 
+    /// The environment each process substitution runs in, with the PATH and
+    /// variables of the directives before it. Those are resolved but not
+    /// checked here: one that fails is reported when the file is loaded.
+    pub fn substitution_environments(
+        commands: &[Command],
+        working_dir: &Path,
+        settings: &Settings,
+    ) -> Vec<(String, Environment)> {
+        let mut environments = Vec::new();
+        let mut environment = Environment::inherited();
+
+        for cmd in commands {
+            match cmd {
+                Command::ProcessSubstitution { command } => {
+                    environments.push((command.clone(), environment.clone()));
+                }
+                _ => {
+                    let _ = Self::command_to_shell(cmd, working_dir, settings, &mut environment);
+                }
+            }
+        }
+
+        environments
+    }
+
+    /// Collect the local files that the generated script will source
+    pub fn sourced_files(commands: &[Command], working_dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+
+        for cmd in commands {
+            match cmd {
+                Command::Source { path } => {
+                    files.push(Self::resolve_path(path, working_dir)?);
+                }
+                Command::PythonVenv { path, .. } => {
+                    // A venv that can't be discovered is reported when it is activated
+                    if let Ok(venv) = Python::venv(path, working_dir) {
+                        files.push(venv.path.join("bin").join("activate"));
+                    }
+                }
+                Command::CondaEnv { env } => {
+                    // The environment may not exist yet; that is reported when it is activated
+                    if let Ok(env) = Conda::resolve(env, working_dir) {
+                        files.extend(env.activate_scripts);
+                        files.extend(env.deactivate_scripts);
+                    }
+                }
+                // Not part of the approved content, but it decides what goes on PATH
+                Command::UseToolVersions { file } => {
+                    files.push(ToolVersions::file(file.as_deref(), working_dir));
+                }
+                // Likewise for the Nix files the environment is evaluated from
+                Command::UseNix { file } => {
+                    let file = Nix::shell_file(file.as_deref(), working_dir);
+                    files.extend(Nix::local_files(Shell::NixShell(&file), working_dir));
+                }
+                Command::UseFlake { reference } => {
+                    let reference = reference.as_deref().unwrap_or(".");
+                    files.extend(Nix::local_files(Shell::Flake(reference), working_dir));
+                }
+                Command::ProcessSubstitution { .. }
+                | Command::UseNode { .. }
+                | Command::UseRust { .. }
+                | Command::UseJdk { .. }
+                | Command::UseRuby { .. } => {}
+            }
+        }
+
+        Ok(files)
+    }
+
     /// Convert a Command to a shell script line
-    fn command_to_shell(cmd: &Command, working_dir: &Path) -> Result<String> {
+    fn command_to_shell(
+        cmd: &Command,
+        working_dir: &Path,
+        settings: &Settings,
+        environment: &mut Environment,
+    ) -> Result<String> {
         match cmd {
             Command::Source { path } => {
                 let resolved_path = Self::resolve_path(path, working_dir)?;
                 Ok(format!("source '{}'", resolved_path.display()))
             }
-            Command::PythonVenv { path } => {
-                let resolved_path = Self::resolve_path(path, working_dir)?;
-                let activate_script = resolved_path.join("bin").join("activate");
+            Command::PythonVenv { path, create, .. } => {
+                let hint = if *create {
+                    "\nRun 'durrrrrenv venv create' to create it"
+                } else {
+                    ""
+                };
+                let venv = Python::venv(path, working_dir).map_err(|e| anyhow::anyhow!("{:#}{}", e, hint))?;
+                let activate_script = venv.path.join("bin").join("activate");
 
                 if !activate_script.exists() {
                     anyhow::bail!(
-                        "Python venv activate script not found: {}",
-                        activate_script.display()
+                        "Python venv activate script not found: {}{}",
+                        activate_script.display(),
+                        hint
                     );
                 }
 
+                // What activate sets that later commands care about
+                environment.set("VIRTUAL_ENV", &venv.path.display().to_string());
+                environment.add_path(&venv.path.join("bin"));
+
                 Ok(format!("source '{}'", activate_script.display()))
             }
+            Command::ProcessSubstitution { command } if settings.sandbox.process_substitution => {
+                // Run the command ourselves under the sandbox and hand the shell its output
+                let timeout = Duration::from_secs(settings.sandbox.timeout_secs);
+                let output = Sandbox::run(command, working_dir, &environment.vars, timeout)?;
+                Ok(format!("eval {}", Self::shell_quote(&output)))
+            }
             Command::ProcessSubstitution { command } => {
                 // For process substitution, we need to execute the command and verify it works
                 // The actual substitution happens in the shell
                 Ok(format!("source <({})", command))
             }
+            Command::UseNode { version } => {
+                let node = Node::resolve(version.as_deref(), working_dir)?;
+                Ok(environment.prepend_path(&node.bin))
+            }
+            Command::UseRust { toolchain } => {
+                let toolchain = Rust::resolve(toolchain.as_deref(), working_dir)?;
+                Ok(format!(
+                    "{}\n{}",
+                    environment.export("RUSTUP_TOOLCHAIN", &toolchain.name),
+                    environment.prepend_path(&toolchain.dir.join("bin"))
+                ))
+            }
+            Command::UseJdk { version } => {
+                let jdk = Jdk::resolve(version)?;
+                Ok(format!(
+                    "{}\n{}",
+                    environment.export("JAVA_HOME", &jdk.home.display().to_string()),
+                    environment.prepend_path(&jdk.home.join("bin"))
+                ))
+            }
+            Command::UseRuby { version } => {
+                let ruby = Ruby::resolve(version.as_deref(), working_dir)?;
+                let gem_home = ruby.gem_home.display().to_string();
+                let gem_path = match &ruby.gem_root {
+                    Some(root) => format!("{}:{}", gem_home, root.display()),
+                    None => gem_home.clone(),
+                };
+
+                // Gem executables go in front of the Ruby's own bin directory
+                Ok([
+                    environment.export("GEM_HOME", &gem_home),
+                    environment.export("GEM_PATH", &gem_path),
+                    environment.prepend_path(&ruby.ruby.prefix.join("bin")),
+                    environment.prepend_path(&ruby.gem_home.join("bin")),
+                ]
+                .join("\n"))
+            }
+            Command::CondaEnv { env } => {
+                let env = Conda::resolve(env, working_dir)?;
+                let prefix = env.prefix.display().to_string();
+
+                let mut lines = vec![
+                    environment.export("CONDA_PREFIX", &prefix),
+                    environment.export("CONDA_DEFAULT_ENV", &env.name),
+                    environment.export("CONDA_PROMPT_MODIFIER", &format!("({}) ", env.name)),
+                    environment.prepend_path(&env.prefix.join("bin")),
+                ];
+                lines.extend(
+                    env.env_vars
+                        .iter()
+                        .filter(|(name, _)| Self::is_variable_name(name))
+                        .map(|(name, value)| environment.export(name, value)),
+                );
+                lines.extend(
+                    env.activate_scripts
+                        .iter()
+                        .map(|script| format!("source {}", Self::shell_quote(&script.display().to_string()))),
+                );
+                // deactivate.d scripts run on unload while CONDA_PREFIX is still set
+                lines.extend(env.deactivate_scripts.iter().map(|script| {
+                    let command = format!("source {}", Self::shell_quote(&script.display().to_string()));
+                    format!("_durrrrrenv_on_unload {}", Self::shell_quote(&command))
+                }));
+                Ok(lines.join("\n"))
+            }
+            Command::UseNix { file } => {
+                let file = Nix::shell_file(file.as_deref(), working_dir);
+                let env = Nix::dev_env(Shell::NixShell(&file), working_dir)?;
+                Ok(Self::replay_dev_env(&env, environment))
+            }
+            Command::UseFlake { reference } => {
+                let env = Nix::dev_env(Shell::Flake(reference.as_deref().unwrap_or(".")), working_dir)?;
+                Ok(Self::replay_dev_env(&env, environment))
+            }
+            Command::UseToolVersions { file } => {
+                // Missing tools are left out; status and check --verbose list them
+                let resolution = ToolVersions::resolve(file.as_deref(), working_dir)?;
+                let lines: Vec<String> = resolution
+                    .tools
+                    .iter()
+                    .rev()
+                    .map(|tool| environment.prepend_path(&tool.bin))
+                    .collect();
+                Ok(lines.join("\n"))
+            }
         }
     }
 
+    /// Shell lines applying what a Nix shell changes about the current environment
+    fn replay_dev_env(env: &DevEnv, environment: &mut Environment) -> String {
+        let (path, variables) = env.changes();
+        let mut lines = Vec::new();
+        // Prepending in reverse keeps the shell's PATH order
+        lines.extend(path.into_iter().rev().map(|dir| environment.prepend_path(dir)));
+        // Exported functions come through as e.g. BASH_FUNC_x%%, which no shell can set
+        lines.extend(
+            variables
+                .into_iter()
+                .filter(|(name, _)| Self::is_variable_name(name))
+                .map(|(name, value)| environment.export(name, value)),
+        );
+        lines.join("\n")
+    }
+
+    /// Shell line setting a variable through the hook, which restores the
+    /// previous value when the environment is unloaded
+    pub fn export(name: &str, value: &str) -> String {
+        format!("_durrrrrenv_export {} {}", name, Self::shell_quote(value))
+    }
+
+    /// Shell line putting a directory in front of PATH through the hook,
+    /// which takes it out again when the environment is unloaded
+    pub fn prepend_path(dir: &Path) -> String {
+        format!("_durrrrrenv_path_add {}", Self::shell_quote(&dir.display().to_string()))
+    }
+
+    /// Whether a name can be set as a shell variable: `[A-Za-z_][A-Za-z0-9_]*`.
+    /// Names from conda's and Nix's output are not written in the env file,
+    /// so anything else is skipped rather than put into the script.
+    pub fn is_variable_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    /// Quote a string for safe use as a single shell word
+    pub fn shell_quote(value: &str) -> String {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+
     /// Resolve a path relative to the working directory
-    fn resolve_path(path: &str, working_dir: &Path) -> Result<std::path::PathBuf> {
+    pub fn resolve_path(path: &str, working_dir: &Path) -> Result<PathBuf> {
         // Handle tilde expansion
-        let expanded = if path.starts_with("~/") {
+        let expanded = if let Some(rest) = path.strip_prefix("~/") {
             let home = dirs::home_dir()
                 .context("Failed to determine home directory")?;
-            home.join(&path[2..])
+            home.join(rest)
         } else if path.starts_with('~') {
             // Handle ~username - for now just return as-is and let the shell handle it
-            return Ok(std::path::PathBuf::from(path));
+            return Ok(PathBuf::from(path));
         } else if path.starts_with('/') {
             // Absolute path
-            std::path::PathBuf::from(path)
+            PathBuf::from(path)
         } else {
             // Relative path
             working_dir.join(path)
@@ -66,5 +344,66 @@ impl Executor {
 
         Ok(expanded)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use std::fs;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(Executor::shell_quote("plain"), "'plain'");
+        assert_eq!(Executor::shell_quote("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn test_environment_follows_earlier_directives() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(".venv/bin")).unwrap();
+        fs::write(dir.path().join(".venv/bin/activate"), "").unwrap();
+        let commands = Parser::parse("python_venv .venv\n").unwrap();
+
+        let mut environment = Environment::inherited();
+        environment.set("PATH", "/usr/bin");
+        Executor::command_to_shell(&commands[0], dir.path(), &Settings::default(), &mut environment).unwrap();
+
+        let venv = dir.path().join(".venv");
+        assert_eq!(environment.path(), Some(format!("{}/bin:/usr/bin", venv.display()).as_str()));
+        assert_eq!(environment.vars["VIRTUAL_ENV"], venv.display().to_string());
+    }
 
+    #[test]
+    fn test_sourced_files_include_nix_inputs() {
+        let dir = Path::new("/srv/app");
+        let commands = Parser::parse("use_nix\nuse_flake ./nix#dev\nuse_flake github:org/repo\n").unwrap();
+
+        assert_eq!(
+            Executor::sourced_files(&commands, dir).unwrap(),
+            vec![
+                dir.join("shell.nix"),
+                dir.join("./nix/flake.nix"),
+                dir.join("./nix/flake.lock"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_variable_name() {
+        assert!(Executor::is_variable_name("JAVA_HOME"));
+        assert!(Executor::is_variable_name("_private1"));
+        assert!(!Executor::is_variable_name("1ST"));
+        assert!(!Executor::is_variable_name("BASH_FUNC_x%%"));
+        assert!(!Executor::is_variable_name("A;rm -rf ~"));
+        assert!(!Executor::is_variable_name(""));
+    }
+
+    #[test]
+    fn test_prepend_path() {
+        assert_eq!(
+            Executor::prepend_path(Path::new("/opt/node/bin")),
+            "_durrrrrenv_path_add '/opt/node/bin'"
+        );
+    }
 }
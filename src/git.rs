@@ -0,0 +1,209 @@
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::Command as ProcessCommand;
+
+/// A git repository (or worktree of one) containing a directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Repository {
+    /// Top-level directory of this checkout or worktree
+    pub root: PathBuf,
+    /// Stable identity shared by all worktrees and clones: origin URL or root commit
+    pub identity: String,
+}
+
+impl Repository {
+    /// Find the repository containing `dir` without spawning git when possible
+    pub fn discover(dir: &Path) -> Option<Self> {
+        let root = dir.ancestors().find(|d| d.join(".git").exists())?.to_path_buf();
+        let common_dir = Self::common_dir(&root)?;
+
+        let identity = Self::origin_url(&common_dir)
+            .map(|url| format!("origin:{}", Self::normalize_url(&url)))
+            .or_else(|| Self::cached_root_commit(&root, &common_dir).map(|sha| format!("root:{}", sha)))?;
+
+        Some(Self { root, identity })
+    }
+
+    /// Path of `dir` relative to the repository root
+    pub fn relative_path(&self, dir: &Path) -> Option<PathBuf> {
+        dir.strip_prefix(&self.root).ok().map(Path::to_path_buf)
+    }
+
+    /// Locate the git directory shared by all worktrees
+    fn common_dir(root: &Path) -> Option<PathBuf> {
+        let dot_git = root.join(".git");
+
+        if dot_git.is_dir() {
+            return Some(dot_git);
+        }
+
+        // Worktrees and submodules have a .git file pointing at their git directory
+        let contents = fs::read_to_string(&dot_git).ok()?;
+        let git_dir = contents.trim().strip_prefix("gitdir:")?.trim();
+        let git_dir = root.join(git_dir);
+
+        match fs::read_to_string(git_dir.join("commondir")) {
+            Ok(common) => Some(git_dir.join(common.trim())),
+            Err(_) => Some(git_dir),
+        }
+    }
+
+    /// Read the origin remote URL from a git config file
+    fn origin_url(common_dir: &Path) -> Option<String> {
+        let config = fs::read_to_string(common_dir.join("config")).ok()?;
+        Self::parse_origin_url(&config)
+    }
+
+    fn parse_origin_url(config: &str) -> Option<String> {
+        let mut in_origin = false;
+
+        for line in config.lines() {
+            let line = line.trim();
+
+            if line.starts_with('[') {
+                in_origin = line.replace(' ', "") == "[remote\"origin\"]";
+                continue;
+            }
+
+            if in_origin {
+                if let Some((key, value)) = line.split_once('=') {
+                    if key.trim() == "url" {
+                        return Some(value.trim().to_string());
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Treat `https://host/org/repo.git` and `https://host/org/repo/` as the same remote
+    fn normalize_url(url: &str) -> String {
+        let url = url.trim_end_matches('/');
+        url.strip_suffix(".git").unwrap_or(url).to_string()
+    }
+
+    /// Root commit, remembered per git directory so that changing into a
+    /// repository without an origin doesn't run git every time
+    fn cached_root_commit(root: &Path, common_dir: &Path) -> Option<String> {
+        // The inode tells a re-created repository apart from the one that was cached
+        let inode = fs::metadata(common_dir).ok()?.ino();
+        let key = Sha256::digest(common_dir.to_string_lossy().as_bytes());
+        let cache_file = dirs::cache_dir()?
+            .join("durrrrrenv")
+            .join("git")
+            .join(format!("{:x}", key));
+
+        if let Some((cached_inode, sha)) = fs::read_to_string(&cache_file)
+            .ok()
+            .and_then(|contents| Self::parse_cache(&contents))
+        {
+            if cached_inode == inode {
+                return Some(sha);
+            }
+        }
+
+        let sha = Self::root_commit(root)?;
+        // A cache that can't be written only costs another git run next time
+        if let Some(parent) = cache_file.parent() {
+            let _ = fs::create_dir_all(parent).and_then(|_| fs::write(&cache_file, format!("{} {}\n", inode, sha)));
+        }
+        Some(sha)
+    }
+
+    fn parse_cache(contents: &str) -> Option<(u64, String)> {
+        let (inode, sha) = contents.trim().split_once(' ')?;
+        Some((inode.parse().ok()?, sha.to_string()))
+    }
+
+    /// First commit of the repository, for repositories without an origin
+    fn root_commit(root: &Path) -> Option<String> {
+        let output = ProcessCommand::new("git")
+            .arg("-C")
+            .arg(root)
+            .args(["rev-list", "--max-parents=0", "HEAD"])
+            .output()
+            .ok()?;
+
+        if !output.status.success() {
+            return None;
+        }
+
+        // Merged histories can have several roots; pick one deterministically
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_origin_url() {
+        let config = r#"
+[core]
+	bare = false
+[remote "upstream"]
+	url = git@github.com:other/repo.git
+[remote "origin"]
+	url = git@github.com:org/repo.git
+	fetch = +refs/heads/*:refs/remotes/origin/*
+"#;
+
+        assert_eq!(
+            Repository::parse_origin_url(config),
+            Some("git@github.com:org/repo.git".to_string())
+        );
+        assert_eq!(Repository::parse_origin_url("[core]\n"), None);
+    }
+
+    #[test]
+    fn test_normalize_url() {
+        assert_eq!(
+            Repository::normalize_url("https://github.com/org/repo.git"),
+            "https://github.com/org/repo"
+        );
+        assert_eq!(
+            Repository::normalize_url("https://github.com/org/repo/"),
+            "https://github.com/org/repo"
+        );
+    }
+
+    #[test]
+    fn test_discover_worktree_shares_identity() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("main");
+        let worktree = dir.path().join("feature");
+
+        fs::create_dir_all(main.join(".git/worktrees/feature")).unwrap();
+        fs::write(
+            main.join(".git/config"),
+            "[remote \"origin\"]\n\turl = https://example.com/org/repo.git\n",
+        )
+        .unwrap();
+        fs::write(main.join(".git/worktrees/feature/commondir"), "../..\n").unwrap();
+
+        fs::create_dir_all(worktree.join("services/api")).unwrap();
+        fs::write(
+            worktree.join(".git"),
+            format!("gitdir: {}\n", main.join(".git/worktrees/feature").display()),
+        )
+        .unwrap();
+
+        let a = Repository::discover(&main).unwrap();
+        let b = Repository::discover(&worktree.join("services/api")).unwrap();
+
+        assert_eq!(a.identity, "origin:https://example.com/org/repo");
+        assert_eq!(a.identity, b.identity);
+        assert_eq!(b.root, worktree);
+        assert_eq!(
+            b.relative_path(&worktree.join("services/api")),
+            Some(PathBuf::from("services/api"))
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// An installed JDK
+#[derive(Debug, Clone)]
+pub struct Installation {
+    /// JAVA_HOME for this JDK
+    pub home: PathBuf,
+    /// JAVA_VERSION from its release file, e.g. "17.0.9" or "1.8.0_392"
+    pub version: String,
+}
+
+pub struct Jdk;
+
+impl Jdk {
+    /// Find the best installed JDK for a requested version like `17`,
+    /// `17.0.9` or `1.8`: the newest one whose version starts with it
+    pub fn resolve(requested: &str) -> Result<Installation> {
+        Self::select(requested, &Self::installations())
+    }
+
+    fn select(requested: &str, installations: &[Installation]) -> Result<Installation> {
+        let wanted = Self::components(requested);
+        if wanted.is_empty() {
+            anyhow::bail!("Invalid JDK version '{}'", requested);
+        }
+
+        if let Some(best) = installations
+            .iter()
+            .filter(|jdk| Self::components(&jdk.version).starts_with(&wanted))
+            .max_by(|a, b| Self::components(&a.version).cmp(&Self::components(&b.version)))
+        {
+            return Ok(best.clone());
+        }
+
+        if installations.is_empty() {
+            anyhow::bail!(
+                "Cannot use JDK {}: no JDKs found under {}",
+                requested,
+                Self::roots()
+                    .iter()
+                    .map(|root| root.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        let installed: Vec<String> = installations
+            .iter()
+            .map(|jdk| format!("{} ({})", jdk.version, jdk.home.display()))
+            .collect();
+        anyhow::bail!(
+            "Cannot use JDK {}: no installed JDK matches. Installed: {}",
+            requested,
+            installed.join(", ")
+        )
+    }
+
+    /// Numeric version components, with the legacy `1.` prefix dropped so
+    /// that 1.8.0_392 and 8 compare alike
+    fn components(version: &str) -> Vec<u64> {
+        let mut components: Vec<u64> = version
+            .split(['.', '_', '+', '-'])
+            .map_while(|part| part.parse().ok())
+            .collect();
+
+        if components.len() > 1 && components[0] == 1 {
+            components.remove(0);
+        }
+        components
+    }
+
+    /// Directories JDKs are commonly installed into
+    fn roots() -> Vec<PathBuf> {
+        let home = dirs::home_dir().unwrap_or_default();
+        let sdkman = env::var_os("SDKMAN_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home.join(".sdkman"));
+
+        vec![
+            PathBuf::from("/usr/lib/jvm"),
+            sdkman.join("candidates/java"),
+            home.join(".jdks"),
+            PathBuf::from("/Library/Java/JavaVirtualMachines"),
+        ]
+    }
+
+    /// Every JDK under the install roots, once each even when reachable
+    /// through several symlinks (default-java, current, ...)
+    fn installations() -> Vec<Installation> {
+        let mut seen = HashSet::new();
+        let mut installations = Vec::new();
+
+        for root in Self::roots() {
+            for jdk in Self::scan(&root) {
+                let canonical = fs::canonicalize(&jdk.home).unwrap_or_else(|_| jdk.home.clone());
+                if seen.insert(canonical) {
+                    installations.push(jdk);
+                }
+            }
+        }
+
+        installations
+    }
+
+    /// JDKs directly below a root, identified by their release file
+    fn scan(root: &Path) -> Vec<Installation> {
+        let Ok(entries) = fs::read_dir(root) else {
+            return Vec::new();
+        };
+
+        let mut installations: Vec<Installation> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                // macOS bundles keep the JDK under Contents/Home
+                let home = [path.join("Contents/Home"), path]
+                    .into_iter()
+                    .find(|home| home.join("release").is_file())?;
+                let version = Self::release_version(&home.join("release"))?;
+                Some(Installation { home, version })
+            })
+            .collect();
+
+        installations.sort_by(|a, b| a.home.cmp(&b.home));
+        installations
+    }
+
+    /// JAVA_VERSION from a JDK's release file
+    fn release_version(release: &Path) -> Option<String> {
+        fs::read_to_string(release)
+            .ok()?
+            .lines()
+            .find_map(|line| line.strip_prefix("JAVA_VERSION="))
+            .map(|value| value.trim().trim_matches('"').to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn install(root: &Path, name: &str, version: &str) {
+        let home = root.join(name);
+        fs::create_dir_all(home.join("bin")).unwrap();
+        fs::write(home.join("release"), format!("IMPLEMENTOR=\"Eclipse\"\nJAVA_VERSION=\"{}\"\n", version)).unwrap();
+    }
+
+    #[test]
+    fn test_select_best_match() {
+        let root = tempfile::tempdir().unwrap();
+        install(root.path(), "java-8-openjdk", "1.8.0_392");
+        install(root.path(), "jdk-17.0.2", "17.0.2");
+        install(root.path(), "jdk-17.0.10", "17.0.10");
+        install(root.path(), "jdk-21", "21.0.1");
+        fs::create_dir_all(root.path().join("not-a-jdk")).unwrap();
+
+        let installations = Jdk::scan(root.path());
+        assert_eq!(installations.len(), 4);
+
+        let pick = |version| Jdk::select(version, &installations).unwrap().version;
+        assert_eq!(pick("17"), "17.0.10");
+        assert_eq!(pick("17.0.2"), "17.0.2");
+        assert_eq!(pick("8"), "1.8.0_392");
+        assert_eq!(pick("1.8"), "1.8.0_392");
+
+        let error = Jdk::select("11", &installations).unwrap_err().to_string();
+        assert!(error.contains("no installed JDK matches"));
+    }
+}
@@ -1,18 +1,52 @@
+mod audit;
+mod conda;
 mod config;
+mod diff;
 mod executor;
+mod git;
+mod jdk;
+mod manifests;
+mod nix;
+mod node;
 mod parser;
+mod paths;
+mod permissions;
+mod pinning;
+mod policy;
+mod prompt;
+mod python;
+mod review;
+mod ruby;
+mod rust;
+mod sandbox;
+mod settings;
+mod signing;
+mod tool_versions;
+mod trust;
 
 use anyhow::{Context, Result};
 use clap::{Parser as ClapParser, Subcommand};
-use std::env;
 use std::fs;
 use std::io::{self, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
-use config::Config;
+use audit::{AuditEvent, AuditLog, EventKind};
+use config::{Config, PinnedBinary};
 use executor::Executor;
-use parser::Parser;
+use git::Repository;
+use manifests::Manifests;
+use parser::{Command, Parser};
+use permissions::Permissions;
+use pinning::Pinning;
+use policy::Policy;
+use prompt::Tty;
+use python::Python;
+use review::Review;
+use settings::{Settings, SigningSettings};
+use signing::FileSignature;
+use tool_versions::{Resolution, ToolVersions};
+use trust::{ImportResult, PathMap, TrustExport};
 
 /// Maximum number of parent directories to search up
 const MAX_SEARCH_DEPTH: usize = 5;
@@ -35,12 +69,27 @@ enum Commands {
         /// Enable verbose output with performance metrics
         #[arg(short, long)]
         verbose: bool,
+        /// Ask on the terminal what to do with an unapproved file (used by the zsh hook)
+        #[arg(short, long)]
+        interactive: bool,
     },
     /// Allow the .local_environment file in the current directory
     Allow {
         /// Directory to allow (defaults to current directory)
         #[arg(short, long)]
         dir: Option<PathBuf>,
+        /// Trust this file in every worktree and clone of its git repository
+        #[arg(long)]
+        repo: bool,
+        /// Approve or reject each directive individually
+        #[arg(short, long, conflicts_with = "yes")]
+        interactive: bool,
+        /// Allow without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+        /// Only allow the file if its SHA-256 hash matches (see 'durrrrrenv status')
+        #[arg(long, value_name = "SHA256")]
+        expect_hash: Option<String>,
     },
     /// Deny/remove permission for the current directory
     Deny {
@@ -54,6 +103,59 @@ enum Commands {
         #[arg(short, long)]
         dir: Option<PathBuf>,
     },
+    /// Move the trust of a directory to a new location
+    Mv {
+        /// Directory that was allowed
+        from: PathBuf,
+        /// Directory the project now lives in
+        to: PathBuf,
+    },
+    /// Manage the trust database
+    Trust {
+        #[command(subcommand)]
+        command: TrustCommands,
+    },
+    /// Manage the Python venvs of python_venv directives
+    Venv {
+        #[command(subcommand)]
+        command: VenvCommands,
+    },
+    /// Generate a key for signing .local_environment files
+    Keygen {
+        /// Where to write the secret key (defaults to ~/.config/durrrrrenv/signing.key)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Write a detached signature next to the .local_environment file
+    Sign {
+        /// Directory containing the file to sign (defaults to current directory)
+        #[arg(short, long)]
+        dir: Option<PathBuf>,
+        /// Secret key to sign with (defaults to ~/.config/durrrrrenv/signing.key)
+        #[arg(short, long)]
+        key: Option<PathBuf>,
+    },
+    /// Show the audit log of trust and load events
+    Log {
+        /// Only show events for this directory and its subdirectories
+        #[arg(short, long)]
+        dir: Option<PathBuf>,
+        /// Only show events after this time (e.g. 2h, 1d, 2024-03-01, "2024-03-01 14:00")
+        #[arg(long)]
+        since: Option<String>,
+        /// Only show events before this time
+        #[arg(long)]
+        until: Option<String>,
+        /// Print raw JSON lines
+        #[arg(long)]
+        json: bool,
+    },
+    /// Record that the hook unloaded an environment (used by the zsh hook)
+    #[command(hide = true)]
+    Unload {
+        #[arg(short, long)]
+        dir: PathBuf,
+    },
     /// Show the path to the zsh hook script
     Hook,
     /// Benchmark performance
@@ -67,35 +169,97 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+enum TrustCommands {
+    /// Export approved directories and file hashes to a portable file
+    Export {
+        /// File to write (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Import an exported trust file, skipping entries whose file content differs
+    Import {
+        /// File produced by 'durrrrrenv trust export'
+        file: PathBuf,
+        /// Rewrite path prefixes, e.g. --map /home/old=/Users/new (repeatable)
+        #[arg(long, value_name = "OLD_PREFIX=NEW_PREFIX")]
+        map: Vec<String>,
+    },
+    /// Validate the trust database
+    Verify,
+}
+
+#[derive(Subcommand)]
+enum VenvCommands {
+    /// Create the missing venvs of python_venv --create directives
+    Create {
+        /// Project directory (defaults to current directory)
+        #[arg(short, long)]
+        dir: Option<PathBuf>,
+        /// Create without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Install the project's current dependencies into its venv and record them
+    Sync {
+        /// Project directory (defaults to current directory)
+        #[arg(short, long)]
+        dir: Option<PathBuf>,
+    },
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Check { dir, verbose } => check_command(dir, verbose),
-        Commands::Allow { dir } => allow_command(dir),
+        Commands::Check { dir, verbose, interactive } => check_command(dir, verbose, interactive),
+        Commands::Allow { dir, repo, interactive, yes, expect_hash } => {
+            allow_command(dir, repo, interactive, yes, expect_hash)
+        }
         Commands::Deny { dir } => deny_command(dir),
         Commands::Status { dir } => status_command(dir),
+        Commands::Mv { from, to } => mv_command(from, to),
+        Commands::Trust { command } => match command {
+            TrustCommands::Export { output } => trust_export_command(output),
+            TrustCommands::Import { file, map } => trust_import_command(file, map),
+            TrustCommands::Verify => trust_verify_command(),
+        },
+        Commands::Venv { command } => match command {
+            VenvCommands::Create { dir, yes } => venv_create_command(dir, yes),
+            VenvCommands::Sync { dir } => venv_sync_command(dir),
+        },
+        Commands::Keygen { output } => keygen_command(output),
+        Commands::Sign { dir, key } => sign_command(dir, key),
+        Commands::Log { dir, since, until, json } => log_command(dir, since, until, json),
+        Commands::Unload { dir } => unload_command(dir),
         Commands::Hook => hook_command(),
         Commands::Bench { dir, iterations } => bench_command(dir, iterations),
     }
 }
 
+/// Resolve the directory to operate on as a logical (symlink-preserving) path
 fn get_working_dir(dir: Option<PathBuf>) -> Result<PathBuf> {
     if let Some(d) = dir {
-        Ok(d)
+        paths::absolute_logical(&d)
     } else {
-        env::current_dir().context("Failed to get current directory")
+        paths::logical_current_dir()
     }
 }
 
-fn get_env_file_path(dir: &PathBuf) -> PathBuf {
+/// Emit the directory markers the hook uses to track the active environment
+fn print_dir_markers(source_dir: &Path) {
+    println!("DURRRRRENV_DIR={}", source_dir.display());
+    println!("DURRRRRENV_REAL_DIR={}", paths::canonical(source_dir).display());
+}
+
+fn get_env_file_path(dir: &Path) -> PathBuf {
     dir.join(".local_environment")
 }
 
 /// Search up the directory tree for a .local_environment file
 /// Returns (env_file_path, source_directory, depth) if found
-fn find_env_file_in_parents(start_dir: &PathBuf) -> Option<(PathBuf, PathBuf, usize)> {
-    let mut current = start_dir.as_path();
+fn find_env_file_in_parents(start_dir: &Path) -> Option<(PathBuf, PathBuf, usize)> {
+    let mut current = start_dir;
     let mut depth = 0;
 
     while depth < MAX_SEARCH_DEPTH {
@@ -118,7 +282,26 @@ fn find_env_file_in_parents(start_dir: &PathBuf) -> Option<(PathBuf, PathBuf, us
     None // Exceeded max search depth
 }
 
-fn check_command(dir: Option<PathBuf>, verbose: bool) -> Result<()> {
+/// Make sure nobody but the current user or root can modify the env file
+/// or any local file it sources
+fn verify_permissions(
+    env_file: &Path,
+    commands: &[Command],
+    source_dir: &Path,
+    settings: &Settings,
+) -> Result<()> {
+    Permissions::verify(env_file, &settings.permissions)?;
+
+    for file in Executor::sourced_files(commands, source_dir)? {
+        if file.exists() {
+            Permissions::verify(&file, &settings.permissions)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn check_command(dir: Option<PathBuf>, verbose: bool, interactive: bool) -> Result<()> {
     let start_time = if verbose { Some(Instant::now()) } else { None };
 
     let working_dir = get_working_dir(dir)?;
@@ -145,19 +328,131 @@ fn check_command(dir: Option<PathBuf>, verbose: bool) -> Result<()> {
 
     let content = fs::read_to_string(&env_file)
         .context("Failed to read .local_environment file")?;
+    let file_hash = Config::hash_content(&content);
 
-    let config = Config::load()?;
+    let mut config = Config::load()?;
+    let settings = Settings::load()?;
+    let policy = Policy::load()?;
+    let keyring = policy.keyring(&settings.signing);
+
+    let mut source = trust_source(&config, &keyring, &env_file, &source_dir, &content);
+
+    if source.is_none() {
+        if skipped_for_session(&source_dir) {
+            return Ok(());
+        }
+
+        if config.is_denied(&source_dir, &content) {
+            if verbose {
+                eprintln!("durrrrrenv: .local_environment in {} was denied", source_dir.display());
+            }
+            AuditEvent::new(EventKind::Refuse, &source_dir)
+                .with_hash(file_hash)
+                .with_reason("denied")
+                .record();
+            return Ok(());
+        }
+
+        if offer_moved_trust(&mut config, &source_dir, &content, interactive)? {
+            source = Some(TrustSource::Directory);
+        } else if let Some(mut tty) = interactive.then(Tty::open).flatten() {
+            let signer = signed_by(&keyring, &env_file, &content);
+            if !prompt_unapproved(&mut tty, &mut config, &env_file, &source_dir, &content, signer)? {
+                return Ok(());
+            }
+            source = Some(TrustSource::Directory);
+
+            // Right away, so a fresh clone is usable after a single cd. A venv
+            // that couldn't be created is reported again when loading.
+            let commands = Parser::parse(&content)?;
+            if let Err(e) = create_missing_venvs(&mut Some(tty), false, &commands, &source_dir) {
+                eprintln!("durrrrrenv: {:#}", e);
+            }
+            config = Config::load()?;
+        }
+    }
+
+    if let Some(source) = source {
+        // Parse and execute, leaving out directives the user rejected
+        let (commands, skipped) = approved_commands(&config, &source, &source_dir, Parser::parse(&content)?);
+        for directive in &skipped {
+            eprintln!("durrrrrenv: Skipping directive that was not approved: {}", directive);
+        }
+
+        let refuse = |reason: String| {
+            AuditEvent::new(EventKind::Refuse, &source_dir)
+                .with_hash(file_hash.clone())
+                .with_reason(reason)
+                .record();
+        };
+
+        if let Err(e) = verify_permissions(&env_file, &commands, &source_dir, &settings) {
+            eprintln!("durrrrrenv: Refusing to load {}: {}", env_file.display(), e);
+            refuse(format!("unsafe permissions: {}", e));
+            return Ok(());
+        }
+
+        let violations = policy.violations(&env_file, &content, &commands, &source_dir, &settings.signing);
+        if !violations.is_empty() {
+            eprintln!("durrrrrenv: Refusing to load {}: blocked by system policy", env_file.display());
+            for violation in &violations {
+                eprintln!("durrrrrenv:   {}", violation);
+            }
+            refuse(format!("system policy: {}", violations.join("; ")));
+            return Ok(());
+        }
+
+        if let Some(pins) = pinned_binaries(&config, &source, &source_dir) {
+            let differences = Pinning::differences(&pins, &commands, &source_dir, &settings);
+            if !differences.is_empty() {
+                let refuse_mismatch = settings.pinning.refuse_on_mismatch;
+                for difference in &differences {
+                    eprintln!(
+                        "durrrrrenv: {}: {}",
+                        if refuse_mismatch { "Refusing to load" } else { "Warning" },
+                        difference
+                    );
+                }
+                if refuse_mismatch {
+                    eprintln!("durrrrrenv: Run 'durrrrrenv allow' to approve the new binaries");
+                    refuse(format!("pinned binary changed: {}", differences.join("; ")));
+                    return Ok(());
+                }
+            }
+        }
 
-    if config.is_allowed(&source_dir, &content) {
-        // Parse and execute
-        let commands = Parser::parse(&content)?;
-        let script = Executor::generate_shell_script(&commands, &source_dir)?;
+        let script = match Executor::generate_shell_script(&commands, &source_dir, &settings) {
+            Ok(script) => script,
+            Err(e) => {
+                for line in format!("{:#}", e).lines() {
+                    eprintln!("durrrrrenv: {}", line);
+                }
+                refuse(format!("{:#}", e));
+                return Ok(());
+            }
+        };
+
+        let resolutions = tool_versions(&commands, &source_dir);
+        for warning in stale_venv_warnings(&commands, &source_dir)
+            .into_iter()
+            .chain(ignored_tool_warnings(&resolutions))
+        {
+            eprintln!("durrrrrenv: Warning: {}", warning);
+        }
 
         // Output the source directory first (for the hook to track), then the script
-        println!("DURRRRRENV_DIR={}", source_dir.display());
+        print_dir_markers(&source_dir);
         print!("{}", script);
 
+        AuditEvent::new(EventKind::Load, &source_dir)
+            .with_hash(file_hash)
+            .with_commands(&commands)
+            .record();
+
         if verbose {
+            for tool in missing_tools(&resolutions) {
+                eprintln!("durrrrrenv: Not installed: {}", tool);
+            }
             eprintln!("durrrrrenv: Total time: {:?}", start_time.unwrap().elapsed());
         }
     } else {
@@ -168,98 +463,649 @@ fn check_command(dir: Option<PathBuf>, verbose: bool) -> Result<()> {
         eprintln!("---");
         eprintln!("{}", content);
         eprintln!("---");
+
+        AuditEvent::new(EventKind::Refuse, &source_dir)
+            .with_hash(file_hash)
+            .with_reason("not allowed")
+            .record();
     }
 
     Ok(())
 }
 
-fn allow_command(dir: Option<PathBuf>) -> Result<()> {
-    let working_dir = get_working_dir(dir)?;
-    let env_file = get_env_file_path(&working_dir);
+/// How an env file came to be trusted
+enum TrustSource {
+    /// Signed by a key in the user's keyring
+    Signature(String),
+    /// Allowed for this directory
+    Directory,
+    /// Allowed for every checkout of its git repository
+    Repository,
+}
+
+/// Check for a trusted signature first, then directory trust, then repository-wide trust.
+/// A signature only counts in directories where the file was allowed once, so a signed
+/// file copied somewhere else does not load without a first-use confirmation.
+fn trust_source(
+    config: &Config,
+    keyring: &SigningSettings,
+    env_file: &Path,
+    source_dir: &Path,
+    content: &str,
+) -> Option<TrustSource> {
+    if config.dir_info(source_dir).is_some() {
+        if let Some(key_name) = signed_by(keyring, env_file, content) {
+            return Some(TrustSource::Signature(key_name));
+        }
+    }
+
+    if config.is_allowed(source_dir, content) {
+        return Some(TrustSource::Directory);
+    }
+
+    if config.repo_trust.is_empty() {
+        return None;
+    }
+
+    match Repository::discover(source_dir) {
+        Some(repo) if config.is_allowed_in_repo(&repo, source_dir, content) => {
+            Some(TrustSource::Repository)
+        }
+        _ => None,
+    }
+}
+
+/// Name of the trusted key that signed the file, if any
+fn signed_by(keyring: &SigningSettings, env_file: &Path, content: &str) -> Option<String> {
+    if keyring.trusted_keys.is_empty() {
+        return None;
+    }
+    signing::verify_env_file(env_file, content, keyring).ok().flatten()
+}
+
+/// Directives rejected when the file was allowed through this trust source
+fn skipped_directives(config: &Config, source: &TrustSource, source_dir: &Path) -> Vec<String> {
+    match source {
+        TrustSource::Signature(_) => Vec::new(),
+        TrustSource::Directory => config
+            .dir_info(source_dir)
+            .map(|info| info.skipped_directives.clone())
+            .unwrap_or_default(),
+        TrustSource::Repository => Repository::discover(source_dir)
+            .and_then(|repo| config.repo_info(&repo, source_dir).map(|info| info.skipped_directives.clone()))
+            .unwrap_or_default(),
+    }
+}
+
+/// The file's directives minus the ones rejected when it was allowed through
+/// this trust source, and the rejected ones as written
+fn approved_commands(
+    config: &Config,
+    source: &TrustSource,
+    source_dir: &Path,
+    commands: Vec<Command>,
+) -> (Vec<Command>, Vec<String>) {
+    let skipped = skipped_directives(config, source, source_dir);
+    let commands = commands
+        .into_iter()
+        .filter(|cmd| !skipped.contains(&cmd.to_string()))
+        .collect();
+    (commands, skipped)
+}
+
+/// Binaries pinned when the file was allowed through this trust source. Signed
+/// files use the pins from when they were first allowed in the directory.
+fn pinned_binaries(config: &Config, source: &TrustSource, source_dir: &Path) -> Option<Vec<PinnedBinary>> {
+    match source {
+        TrustSource::Signature(_) | TrustSource::Directory => {
+            config.dir_info(source_dir).map(|info| info.pinned_binaries.clone())
+        }
+        TrustSource::Repository => Repository::discover(source_dir)
+            .and_then(|repo| config.repo_info(&repo, source_dir).map(|info| info.pinned_binaries.clone())),
+    }
+}
+
+/// Read a y/N answer from the terminal, or from stdin when there is none
+fn confirm(tty: &mut Option<Tty>, prompt: &str) -> Result<bool> {
+    let response = match tty {
+        Some(tty) => tty.read_line(prompt)?,
+        None => {
+            eprint!("{}", prompt);
+            io::stderr().flush()?;
+
+            let mut response = String::new();
+            io::stdin().read_line(&mut response)?;
+            response
+        }
+    };
+
+    Ok(response.trim().eq_ignore_ascii_case("y"))
+}
+
+/// Offer to create the venvs of `python_venv --create` directives that
+/// don't exist yet. With `assume_yes` they are created without asking.
+/// Binaries are pinned again afterwards, as they may resolve into a new venv.
+fn create_missing_venvs(
+    tty: &mut Option<Tty>,
+    assume_yes: bool,
+    commands: &[Command],
+    working_dir: &Path,
+) -> Result<()> {
+    let mut created = false;
+
+    for cmd in commands {
+        let Command::PythonVenv { path, create: true, python } = cmd else {
+            continue;
+        };
+        let Some(venv) = Python::missing_venv(path, working_dir)? else {
+            continue;
+        };
+        let interpreter = Python::interpreter(python.as_deref(), working_dir)?;
+
+        let install = Python::install_source(working_dir)
+            .map(|file| format!(" and install {}", file))
+            .unwrap_or_default();
+        let prompt = format!(
+            "Create Python venv {} with Python {} ({}){}? [y/N]: ",
+            venv.display(),
+            interpreter.version,
+            interpreter.origin,
+            install
+        );
+        if !assume_yes && !confirm(tty, &prompt)? {
+            continue;
+        }
+
+        Python::create_venv(&venv, &interpreter, working_dir)?;
+        Manifests::record(&venv, working_dir)?;
+        created = true;
+    }
+
+    if created {
+        let pins = Pinning::pin(commands, working_dir, &Settings::load()?)?;
+        Config::load()?.update_pins(working_dir, pins)?;
+    }
+
+    Ok(())
+}
+
+/// What the .tool-versions files of use_tool_versions directives resolve to
+fn tool_versions(commands: &[Command], working_dir: &Path) -> Vec<Resolution> {
+    commands
+        .iter()
+        .filter_map(|cmd| match cmd {
+            Command::UseToolVersions { file } => ToolVersions::resolve(file.as_deref(), working_dir).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Tools listed in .tool-versions files that neither asdf nor mise has installed
+fn missing_tools(resolutions: &[Resolution]) -> Vec<String> {
+    resolutions
+        .iter()
+        .flat_map(|resolution| {
+            let file = resolution.file.display().to_string();
+            resolution
+                .missing
+                .iter()
+                .map(move |tool| format!("{} (from {})", tool, file))
+        })
+        .collect()
+}
+
+/// One warning per `path:` version that was not used, and why
+fn ignored_tool_warnings(resolutions: &[Resolution]) -> Vec<String> {
+    resolutions
+        .iter()
+        .flat_map(|resolution| {
+            let file = resolution.file.display().to_string();
+            resolution
+                .ignored
+                .iter()
+                .map(move |ignored| format!("Ignoring {} (from {})", ignored, file))
+        })
+        .collect()
+}
+
+/// One warning per venv whose dependency files changed since it was last created or synced
+fn stale_venv_warnings(commands: &[Command], working_dir: &Path) -> Vec<String> {
+    commands
+        .iter()
+        .filter_map(|cmd| {
+            let Command::PythonVenv { path, .. } = cmd else {
+                return None;
+            };
+            let venv = Python::venv(path, working_dir).ok()?;
+            if !venv.path.join("bin/activate").exists() {
+                return None;
+            }
+            let changed = Manifests::changed(&venv.path, working_dir).ok()?;
+            (!changed.is_empty()).then(|| {
+                format!(
+                    "{} changed since {} was synced; run 'durrrrrenv venv sync'",
+                    changed.join(", "),
+                    venv.path.display()
+                )
+            })
+        })
+        .collect()
+}
+
+/// Whether the hook was told to leave this directory alone for the rest of the session
+fn skipped_for_session(source_dir: &Path) -> bool {
+    std::env::var_os("DURRRRRENV_SKIPPED")
+        .map(|skipped| in_skip_list(&skipped.to_string_lossy(), source_dir))
+        .unwrap_or(false)
+}
+
+/// Whether a directory is one of the newline-separated directories the hook passes along
+fn in_skip_list(skipped: &str, source_dir: &Path) -> bool {
+    skipped.lines().any(|dir| Path::new(dir) == source_dir)
+}
+
+/// Ask what to do with an unapproved file: allow, view, diff, deny or skip.
+/// Returns true if the user allowed it.
+fn prompt_unapproved(
+    tty: &mut Tty,
+    config: &mut Config,
+    env_file: &Path,
+    source_dir: &Path,
+    content: &str,
+    signer: Option<String>,
+) -> Result<bool> {
+    let commands = Parser::parse(content);
+
+    tty.print(&format!(
+        "durrrrrenv: .local_environment in {} is not allowed\n",
+        source_dir.display()
+    ))?;
+    if let Some(key_name) = signer {
+        tty.print(&format!(
+            "durrrrrenv: It is signed by {}; once allowed here, signed updates load without asking\n",
+            key_name
+        ))?;
+    }
+
+    loop {
+        let key = tty.read_key(
+            "durrrrrenv: [a]llow, [v]iew, [d]iff, de[n]y, [s]kip for this session, [q]uit? ",
+        )?;
+
+        match key {
+            'a' => {
+                let commands = match &commands {
+                    Ok(commands) => commands,
+                    Err(e) => {
+                        tty.print(&format!("durrrrrenv: Cannot allow, {}\n", e))?;
+                        return Ok(false);
+                    }
+                };
+                return match approve(config, env_file, source_dir, content, commands, Vec::new(), false) {
+                    Ok(()) => Ok(true),
+                    Err(e) => {
+                        for line in format!("{:#}", e).lines() {
+                            tty.print(&format!("durrrrrenv: {}\n", line))?;
+                        }
+                        Ok(false)
+                    }
+                };
+            }
+            'v' => match &commands {
+                Ok(commands) => tty.page(&Review::summary(content, commands, source_dir))?,
+                Err(e) => tty.page(&format!("{}\n---\n{}\n---\n", e, content.trim_end()))?,
+            },
+            'd' => {
+                let previous = config
+                    .dir_info(source_dir)
+                    .map(|info| info.file_hash.clone())
+                    .or_else(|| {
+                        let repo = Repository::discover(source_dir)?;
+                        config.repo_info(&repo, source_dir).map(|info| info.file_hash.clone())
+                    })
+                    .and_then(|hash| Config::load_snapshot(&hash));
+
+                match previous {
+                    Some(previous) => tty.page(&diff::diff_lines(&previous, content))?,
+                    None => tty.print("durrrrrenv: No previously allowed version to compare against\n")?,
+                }
+            }
+            'n' => {
+                config.deny_persistently(source_dir, content)?;
+                AuditEvent::new(EventKind::Deny, source_dir)
+                    .with_hash(Config::hash_content(content))
+                    .with_reason("until the file changes")
+                    .record();
+                tty.print("durrrrrenv: Denied. You won't be asked again until the file changes.\n")?;
+                return Ok(false);
+            }
+            's' => {
+                // The hook remembers skipped directories for the rest of the session
+                println!("DURRRRRENV_SKIP={}", source_dir.display());
+                return Ok(false);
+            }
+            'q' | '\n' | '\x1b' => return Ok(false),
+            _ => {}
+        }
+    }
+}
+
+/// Offer to carry trust over from a directory that was moved or re-cloned here.
+/// Without `interactive` only says how to. Returns true if the user accepted.
+fn offer_moved_trust(config: &mut Config, source_dir: &Path, content: &str, interactive: bool) -> Result<bool> {
+    let old_path = match config.find_moved(content) {
+        Some(info) => info.path.clone(),
+        None => return Ok(false),
+    };
 
-    if !env_file.exists() {
-        anyhow::bail!("No .local_environment file found in {}", working_dir.display());
+    let mut tty = match interactive.then(Tty::open).flatten() {
+        Some(tty) => tty,
+        None => {
+            eprintln!(
+                "durrrrrenv: This file was allowed in {}, which no longer exists",
+                old_path.display()
+            );
+            eprintln!(
+                "durrrrrenv: Run 'durrrrrenv mv {} {}' to move its trust here",
+                old_path.display(),
+                source_dir.display()
+            );
+            return Ok(false);
+        }
+    };
+
+    let question = format!(
+        "durrrrrenv: .local_environment in {} matches the one allowed in {}, which no longer exists\n\
+         durrrrrenv: Move trust here? [y/N]: ",
+        source_dir.display(),
+        old_path.display()
+    );
+
+    if !tty.confirm(&question)? {
+        return Ok(false);
     }
 
+    config.rekey(&old_path, source_dir)?;
+
+    AuditEvent::new(EventKind::Allow, source_dir)
+        .with_hash(Config::hash_content(content))
+        .with_reason(format!("moved from {}", old_path.display()))
+        .record();
+
+    Ok(true)
+}
+
+fn allow_command(
+    dir: Option<PathBuf>,
+    repo_scope: bool,
+    interactive: bool,
+    yes: bool,
+    expect_hash: Option<String>,
+) -> Result<()> {
+    let start_dir = get_working_dir(dir)?;
+    let (env_file, working_dir, _) = find_env_file_in_parents(&start_dir)
+        .with_context(|| format!("No .local_environment file found in {} or its parents", start_dir.display()))?;
+
     let content = fs::read_to_string(&env_file)
         .context("Failed to read .local_environment file")?;
 
-    // Show content and ask for confirmation
-    eprintln!("Contents of .local_environment:");
-    eprintln!("---");
-    eprintln!("{}", content);
-    eprintln!("---");
-    eprint!("Allow this file to be executed? [y/N]: ");
-    io::stderr().flush()?;
+    if let Some(expected) = expect_hash {
+        let actual = Config::hash_content(&content);
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            anyhow::bail!(
+                "Refusing to allow {}: its hash is {}, expected {}",
+                env_file.display(),
+                actual,
+                expected.trim()
+            );
+        }
+    }
 
-    let mut response = String::new();
-    io::stdin().read_line(&mut response)?;
+    // Parse to validate, and to find everything the file pulls in
+    let mut commands = Parser::parse(&content)?;
+    let mut skipped = Vec::new();
 
-    if response.trim().to_lowercase() != "y" {
-        eprintln!("Aborted.");
-        return Ok(());
-    }
+    if !yes {
+        // Show content, including sourced files, and ask for confirmation
+        let mut tty = Tty::open();
+        let summary = Review::summary(&content, &commands, &working_dir);
+        match &mut tty {
+            Some(tty) => tty.page(&summary)?,
+            None => eprint!("{}", summary),
+        }
+
+        if interactive {
+            let mut approved = Vec::new();
+            for cmd in commands {
+                if confirm(&mut tty, &format!("Approve `{}`? [y/N]: ", cmd))? {
+                    approved.push(cmd);
+                } else if !skipped.contains(&cmd.to_string()) {
+                    skipped.push(cmd.to_string());
+                }
+            }
 
-    // Parse to validate
-    let commands = Parser::parse(&content)?;
+            // A directive written twice is skipped everywhere if rejected once
+            approved.retain(|cmd| !skipped.contains(&cmd.to_string()));
+            if approved.is_empty() {
+                eprintln!("No directives approved. Aborted.");
+                return Ok(());
+            }
+            commands = approved;
+        } else if !confirm(&mut tty, "Allow this file to be executed? [y/N]: ")? {
+            eprintln!("Aborted.");
+            return Ok(());
+        }
+    }
 
     let mut config = Config::load()?;
-    config.allow(&working_dir, &content)?;
+    approve(
+        &mut config,
+        &env_file,
+        &working_dir,
+        &content,
+        &commands,
+        skipped,
+        repo_scope,
+    )?;
 
-    eprintln!("Allowed .local_environment in {}", working_dir.display());
+    // --yes approves the file, not running pip; creating a venv is asked separately
+    if !yes {
+        create_missing_venvs(&mut Tty::open(), false, &commands, &working_dir)?;
+    }
 
     // Generate and output the shell script to execute immediately
-    let script = Executor::generate_shell_script(&commands, &working_dir)?;
+    let settings = Settings::load()?;
+    let script = Executor::generate_shell_script(&commands, &working_dir, &settings)?;
 
     // Output the source directory first (for the hook to track), then the script
-    println!("DURRRRRENV_DIR={}", working_dir.display());
+    print_dir_markers(&working_dir);
     print!("{}", script);
 
     Ok(())
 }
 
+/// Record approval of an env file after checking its permissions and the
+/// system policy. `commands` are the approved directives, `skipped` the
+/// rejected ones.
+fn approve(
+    config: &mut Config,
+    env_file: &Path,
+    working_dir: &Path,
+    content: &str,
+    commands: &[Command],
+    skipped: Vec<String>,
+    repo_scope: bool,
+) -> Result<()> {
+    let settings = Settings::load()?;
+    verify_permissions(env_file, commands, working_dir, &settings)
+        .with_context(|| format!("Refusing to allow {}", env_file.display()))?;
+
+    let violations = Policy::load()?.violations(env_file, content, commands, working_dir, &settings.signing);
+    if !violations.is_empty() {
+        anyhow::bail!(
+            "Refusing to allow {}, blocked by system policy:\n  {}",
+            env_file.display(),
+            violations.join("\n  ")
+        );
+    }
+
+    let mut audit_event = AuditEvent::new(EventKind::Allow, working_dir)
+        .with_hash(Config::hash_content(content))
+        .with_commands(commands);
+    if !skipped.is_empty() {
+        audit_event = audit_event.with_reason(format!("skipped: {}", skipped.join("; ")));
+    }
+
+    let pins = Pinning::pin(commands, working_dir, &settings)?;
+    if repo_scope {
+        let repo = Repository::discover(working_dir)
+            .with_context(|| format!("{} is not inside a git repository", working_dir.display()))?;
+        config.allow_in_repo(&repo, working_dir, content, pins, skipped)?;
+
+        eprintln!(
+            "Allowed .local_environment in every checkout of {} ({})",
+            repo.identity,
+            repo.relative_path(working_dir).unwrap_or_default().display()
+        );
+        audit_event
+            .with_reason(format!("repository-wide for {}", repo.identity))
+            .record();
+    } else {
+        config.allow(working_dir, content, pins, skipped)?;
+
+        eprintln!("Allowed .local_environment in {}", working_dir.display());
+        audit_event.record();
+    }
+
+    // Keep the approved content around for the prompt's diff view
+    if let Err(e) = Config::save_snapshot(content) {
+        eprintln!("durrrrrenv: Warning: {:#}", e);
+    }
+
+    Ok(())
+}
+
 fn deny_command(dir: Option<PathBuf>) -> Result<()> {
-    let working_dir = get_working_dir(dir)?;
+    let start_dir = get_working_dir(dir)?;
+    // Without a file in sight, deny the directory itself so stale trust can still be removed
+    let working_dir = match find_env_file_in_parents(&start_dir) {
+        Some((_, source_dir, _)) => source_dir,
+        None => start_dir,
+    };
 
     let mut config = Config::load()?;
     config.deny(&working_dir)?;
 
+    AuditEvent::new(EventKind::Deny, &working_dir).record();
+
     eprintln!("Denied .local_environment in {}", working_dir.display());
 
     Ok(())
 }
 
 fn status_command(dir: Option<PathBuf>) -> Result<()> {
-    let working_dir = get_working_dir(dir)?;
-    let env_file = get_env_file_path(&working_dir);
-
-    eprintln!("Directory: {}", working_dir.display());
-
-    if !env_file.exists() {
+    let start_dir = get_working_dir(dir)?;
+    let Some((env_file, working_dir, _)) = find_env_file_in_parents(&start_dir) else {
+        eprintln!("Directory: {}", start_dir.display());
         eprintln!("Status: No .local_environment file found");
         return Ok(());
-    }
+    };
+
+    eprintln!("Directory: {}", working_dir.display());
 
     let content = fs::read_to_string(&env_file)
         .context("Failed to read .local_environment file")?;
+    eprintln!("File hash: {}", Config::hash_content(&content));
 
     let config = Config::load()?;
+    let settings = Settings::load()?;
+    let policy = Policy::load()?;
+    let keyring = policy.keyring(&settings.signing);
 
-    if config.is_allowed(&working_dir, &content) {
-        eprintln!("Status: Allowed");
+    if keyring.trusted_keys.is_empty() {
+        if FileSignature::path_for(&env_file).exists() {
+            eprintln!("Signature: Not checked (no trusted keys configured)");
+        }
+    } else {
+        match signing::verify_env_file(&env_file, &content, &keyring) {
+            Ok(Some(key_name)) if config.dir_info(&working_dir).is_none() => {
+                eprintln!("Signature: Signed by {}, not yet allowed in this directory", key_name);
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Signature: Not accepted ({})", e),
+        }
+    }
+
+    if let Ok(commands) = Parser::parse(&content) {
+        let violations = policy.violations(&env_file, &content, &commands, &working_dir, &settings.signing);
+        if !violations.is_empty() {
+            eprintln!("Policy: Blocked by {}", policy::POLICY_PATH);
+            for violation in violations {
+                eprintln!("  {}", violation);
+            }
+        }
+    }
+
+    if let Some(source) = trust_source(&config, &keyring, &env_file, &working_dir, &content) {
+        match &source {
+            TrustSource::Signature(key_name) => eprintln!("Status: Allowed (signed by {})", key_name),
+            TrustSource::Directory => eprintln!("Status: Allowed"),
+            TrustSource::Repository => eprintln!("Status: Allowed (repository-wide)"),
+        }
 
         // Show what commands will be executed
         match Parser::parse(&content) {
             Ok(commands) => {
+                let (commands, skipped) = approved_commands(&config, &source, &working_dir, commands);
+
+                if let Err(e) = verify_permissions(&env_file, &commands, &working_dir, &settings) {
+                    eprintln!("Permissions: Unsafe, will not be loaded ({})", e);
+                }
+
                 eprintln!("\nCommands to execute:");
-                for cmd in commands {
+                for cmd in &commands {
                     eprintln!("  {:?}", cmd);
                 }
+
+                for cmd in &commands {
+                    if let Command::PythonVenv { path, .. } = cmd {
+                        match Python::venv(path, &working_dir) {
+                            Ok(venv) => eprintln!("\nPython venv: {} (from {})", venv.path.display(), venv.tool),
+                            Err(e) => eprintln!("\nPython venv: {:#}", e),
+                        }
+                    }
+                }
+
+                let resolutions = tool_versions(&commands, &working_dir);
+                for warning in stale_venv_warnings(&commands, &working_dir)
+                    .into_iter()
+                    .chain(ignored_tool_warnings(&resolutions))
+                {
+                    eprintln!("Warning: {}", warning);
+                }
+
+                let missing = missing_tools(&resolutions);
+                if !missing.is_empty() {
+                    eprintln!("\nMissing tools (not installed by asdf or mise):");
+                    for tool in missing {
+                        eprintln!("  {}", tool);
+                    }
+                }
+
+                if !skipped.is_empty() {
+                    eprintln!("\nSkipped directives (not approved):");
+                    for directive in skipped {
+                        eprintln!("  {}", directive);
+                    }
+                }
             }
             Err(e) => {
                 eprintln!("Error parsing: {}", e);
             }
         }
+    } else if config.is_denied(&working_dir, &content) {
+        eprintln!("Status: Denied (until the file changes)");
+        eprintln!("\nRun 'durrrrrenv allow' to allow execution");
     } else {
         eprintln!("Status: Not allowed or file has changed");
         eprintln!("\nRun 'durrrrrenv allow' to allow execution");
@@ -268,6 +1114,254 @@ fn status_command(dir: Option<PathBuf>) -> Result<()> {
     Ok(())
 }
 
+fn mv_command(from: PathBuf, to: PathBuf) -> Result<()> {
+    let from = paths::absolute_logical(&from)?;
+    let to = paths::absolute_logical(&to)?;
+
+    if !to.is_dir() {
+        anyhow::bail!("{} is not a directory", to.display());
+    }
+
+    let mut config = Config::load()?;
+    config.rekey(&from, &to)?;
+
+    AuditEvent::new(EventKind::Allow, &to)
+        .with_reason(format!("moved from {}", from.display()))
+        .record();
+
+    eprintln!("Moved trust from {} to {}", from.display(), to.display());
+
+    Ok(())
+}
+
+/// The approved directives of an allowed env file that is also safe to load:
+/// its permissions are fine and the system policy doesn't block it. Returns
+/// the env file, its directory and the directives.
+fn trusted_commands(dir: Option<PathBuf>) -> Result<(PathBuf, PathBuf, Vec<Command>)> {
+    let start_dir = get_working_dir(dir)?;
+    let (env_file, working_dir, _) = find_env_file_in_parents(&start_dir)
+        .with_context(|| format!("No .local_environment file found in {} or its parents", start_dir.display()))?;
+
+    let content = fs::read_to_string(&env_file)
+        .context("Failed to read .local_environment file")?;
+
+    let config = Config::load()?;
+    let settings = Settings::load()?;
+    let policy = Policy::load()?;
+    let keyring = policy.keyring(&settings.signing);
+    let source = trust_source(&config, &keyring, &env_file, &working_dir, &content)
+        .with_context(|| format!("{} is not allowed; run 'durrrrrenv allow' first", env_file.display()))?;
+
+    let (commands, _) = approved_commands(&config, &source, &working_dir, Parser::parse(&content)?);
+
+    verify_permissions(&env_file, &commands, &working_dir, &settings)
+        .with_context(|| format!("Refusing to use {}", env_file.display()))?;
+    let violations = policy.violations(&env_file, &content, &commands, &working_dir, &settings.signing);
+    if !violations.is_empty() {
+        anyhow::bail!(
+            "Refusing to use {}, blocked by system policy:\n  {}",
+            env_file.display(),
+            violations.join("\n  ")
+        );
+    }
+
+    Ok((env_file, working_dir, commands))
+}
+
+fn venv_create_command(dir: Option<PathBuf>, yes: bool) -> Result<()> {
+    // Creating runs pip, so only for files the user approved
+    let (env_file, working_dir, commands) = trusted_commands(dir)?;
+
+    if !commands.iter().any(|cmd| matches!(cmd, Command::PythonVenv { create: true, .. })) {
+        anyhow::bail!("{} has no python_venv --create directive", env_file.display());
+    }
+
+    let mut tty = if yes { None } else { Tty::open() };
+    create_missing_venvs(&mut tty, yes, &commands, &working_dir)
+}
+
+fn venv_sync_command(dir: Option<PathBuf>) -> Result<()> {
+    // Syncing runs the venv's own pip, so only for files the user approved
+    let (env_file, working_dir, commands) = trusted_commands(dir)?;
+
+    let mut synced = 0;
+    for cmd in &commands {
+        let Command::PythonVenv { path, .. } = cmd else {
+            continue;
+        };
+
+        let venv = Python::venv(path, &working_dir)?;
+        if !venv.path.join("bin/activate").exists() {
+            anyhow::bail!("No venv at {}; create it first, e.g. with python_venv --create", venv.path.display());
+        }
+
+        eprintln!("Syncing {} ({})", venv.path.display(), venv.tool);
+        Python::sync(&venv, &working_dir)?;
+        Manifests::record(&venv.path, &working_dir)?;
+        synced += 1;
+    }
+
+    if synced == 0 {
+        anyhow::bail!("{} has no python_venv directive", env_file.display());
+    }
+    Ok(())
+}
+
+fn trust_export_command(output: Option<PathBuf>) -> Result<()> {
+    let config = Config::load()?;
+    let export = TrustExport::from_config(&config);
+    let contents = serde_json::to_string_pretty(&export)
+        .context("Failed to serialize trust export")?;
+
+    match output {
+        Some(path) => {
+            fs::write(&path, contents + "\n")
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            eprintln!("Exported {} entries to {}", export.entries.len(), path.display());
+        }
+        None => println!("{}", contents),
+    }
+
+    Ok(())
+}
+
+fn trust_import_command(file: PathBuf, map: Vec<String>) -> Result<()> {
+    let maps = map
+        .iter()
+        .map(|spec| PathMap::parse(spec))
+        .collect::<Result<Vec<_>>>()?;
+
+    let export = TrustExport::load(&file)?;
+    let mut config = Config::load()?;
+    let results = export.import_into(&mut config, &maps, &Settings::load()?)?;
+
+    let mut imported = 0;
+    for result in &results {
+        match result {
+            ImportResult::Imported(dir, file_hash) => {
+                imported += 1;
+                eprintln!("Imported {}", dir.display());
+                AuditEvent::new(EventKind::Allow, dir)
+                    .with_hash(file_hash.clone())
+                    .with_reason(format!("imported from {}", file.display()))
+                    .record();
+            }
+            ImportResult::Skipped(dir, reason) => {
+                eprintln!("Skipped {}: {}", dir.display(), reason);
+            }
+        }
+    }
+
+    eprintln!("Imported {} of {} entries", imported, results.len());
+
+    Ok(())
+}
+
+fn trust_verify_command() -> Result<()> {
+    let config = Config::load()?;
+    let problems = config.verify();
+
+    eprintln!("Config: {}", Config::config_path()?.display());
+    eprintln!("Version: {}", config.version);
+    eprintln!("Entries: {}", config.allowed_dirs.len());
+
+    let stale = config
+        .allowed_dirs
+        .values()
+        .filter(|info| !info.path.exists())
+        .count();
+    if stale > 0 {
+        eprintln!("Entries for directories that no longer exist: {}", stale);
+    }
+
+    if problems.is_empty() {
+        eprintln!("Status: OK");
+        return Ok(());
+    }
+
+    eprintln!();
+    for problem in &problems {
+        eprintln!("  {}", problem);
+    }
+
+    anyhow::bail!("Found {} problem(s) in the trust database", problems.len())
+}
+
+fn keygen_command(output: Option<PathBuf>) -> Result<()> {
+    let path = match output {
+        Some(path) => path,
+        None => signing::default_key_path()?,
+    };
+
+    let key = signing::generate_key(&path)?;
+
+    eprintln!("Wrote secret key to {}", path.display());
+    eprintln!("Public key (share this with your team):");
+    println!("{}", signing::encode_public_key(&key.verifying_key()));
+
+    Ok(())
+}
+
+fn sign_command(dir: Option<PathBuf>, key: Option<PathBuf>) -> Result<()> {
+    let working_dir = get_working_dir(dir)?;
+    let env_file = get_env_file_path(&working_dir);
+
+    let content = fs::read_to_string(&env_file)
+        .with_context(|| format!("No .local_environment file found in {}", working_dir.display()))?;
+
+    // Refuse to sign something that wouldn't load anyway
+    Parser::parse(&content)?;
+
+    let key_path = match key {
+        Some(path) => path,
+        None => signing::default_key_path()?,
+    };
+    let key = signing::load_key(&key_path)?;
+
+    let sig_path = FileSignature::path_for(&env_file);
+    fs::write(&sig_path, FileSignature::sign(&key, &content).to_text())
+        .with_context(|| format!("Failed to write {}", sig_path.display()))?;
+
+    eprintln!("Wrote {}", sig_path.display());
+
+    Ok(())
+}
+
+fn log_command(dir: Option<PathBuf>, since: Option<String>, until: Option<String>, json: bool) -> Result<()> {
+    let now = audit::now();
+    let since = since.map(|t| audit::parse_time(&t, now)).transpose()?;
+    let until = until.map(|t| audit::parse_time(&t, now)).transpose()?;
+    let dir = dir.map(|d| paths::absolute_logical(&d)).transpose()?;
+
+    let events = AuditLog::read_all()?.into_iter().filter(|event| {
+        since.is_none_or(|since| event.timestamp >= since)
+            && until.is_none_or(|until| event.timestamp <= until)
+            && dir.as_ref().is_none_or(|dir| {
+                event.dir.starts_with(dir) || event.dir.starts_with(paths::canonical(dir))
+            })
+    });
+
+    // Write through a lock so a closed pipe (e.g. `| head`) ends the output quietly
+    let mut out = io::stdout().lock();
+    for event in events {
+        let line = if json {
+            serde_json::to_string(&event)?
+        } else {
+            event.describe()
+        };
+        if writeln!(out, "{}", line).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn unload_command(dir: PathBuf) -> Result<()> {
+    AuditEvent::new(EventKind::Unload, &dir).record();
+    Ok(())
+}
+
 fn hook_command() -> Result<()> {
     // For now, just print the hook script
     let hook_script = include_str!("../hook.zsh");
@@ -318,3 +1412,107 @@ fn bench_command(dir: Option<PathBuf>, iterations: usize) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skipped_directives_are_left_out() {
+        let dir = tempfile::tempdir().unwrap();
+        paths::use_test_dir(dir.path());
+        let project = dir.path().join("app");
+        fs::create_dir_all(project.join(".git")).unwrap();
+        fs::write(project.join(".git/config"), "[remote \"origin\"]\n\turl = https://example.com/app\n").unwrap();
+        let content = "source env.sh\nsource <(west completion zsh)\n";
+        let skipped = vec!["source <(west completion zsh)".to_string()];
+
+        let mut config = Config::default();
+        config.allow(&project, content, Vec::new(), skipped.clone()).unwrap();
+        let repo = Repository::discover(&project).unwrap();
+        config.allow_in_repo(&repo, &project, content, Vec::new(), skipped.clone()).unwrap();
+
+        for source in [TrustSource::Directory, TrustSource::Repository] {
+            let (commands, rejected) = approved_commands(&config, &source, &project, Parser::parse(content).unwrap());
+            assert_eq!(commands, Parser::parse("source env.sh\n").unwrap());
+            assert_eq!(rejected, skipped);
+        }
+
+        // Signed files are never asked about directive by directive
+        let signed = TrustSource::Signature("team".to_string());
+        assert_eq!(approved_commands(&config, &signed, &project, Parser::parse(content).unwrap()).0.len(), 2);
+    }
+
+    #[test]
+    fn test_signature_needs_first_use_in_each_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        paths::use_test_dir(dir.path());
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+        let mut keyring = SigningSettings::default();
+        keyring
+            .trusted_keys
+            .insert("team".to_string(), signing::encode_public_key(&key.verifying_key()));
+
+        let content = "source env.sh\n";
+        let env_file = dir.path().join(".local_environment");
+        fs::write(&env_file, content).unwrap();
+        fs::write(FileSignature::path_for(&env_file), FileSignature::sign(&key, content).to_text()).unwrap();
+
+        // A valid signature alone, e.g. in a copied directory, is not enough
+        let mut config = Config::default();
+        assert!(trust_source(&config, &keyring, &env_file, dir.path(), content).is_none());
+
+        // Signed updates load without asking once the directory was allowed
+        config.allow(dir.path(), "source old.sh\n", Vec::new(), Vec::new()).unwrap();
+        assert!(matches!(
+            trust_source(&config, &keyring, &env_file, dir.path(), content),
+            Some(TrustSource::Signature(name)) if name == "team"
+        ));
+    }
+
+    #[test]
+    fn test_skip_list_matches_whole_directories() {
+        let skipped = "/srv/app\n/srv/other dir";
+        assert!(in_skip_list(skipped, Path::new("/srv/app")));
+        assert!(in_skip_list(skipped, Path::new("/srv/other dir")));
+        assert!(!in_skip_list(skipped, Path::new("/srv/app/sub")));
+        assert!(!in_skip_list(skipped, Path::new("/srv")));
+        assert!(!in_skip_list("", Path::new("/srv/app")));
+    }
+
+    #[test]
+    fn test_allow_and_deny_find_the_file_in_a_parent() {
+        let dir = tempfile::tempdir().unwrap();
+        paths::use_test_dir(dir.path());
+        let project = fs::canonicalize(dir.path()).unwrap().join("app");
+        let sub = project.join("src/deep");
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(project.join("env.sh"), "export A=1\n").unwrap();
+        fs::write(project.join(".local_environment"), "source env.sh\n").unwrap();
+
+        let (env_file, found_dir, depth) = find_env_file_in_parents(&sub).unwrap();
+        assert_eq!((env_file, found_dir, depth), (project.join(".local_environment"), project.clone(), 2));
+
+        let hash = Config::hash_content("source env.sh\n");
+        allow_command(Some(sub.clone()), false, false, true, Some(hash.to_uppercase())).unwrap();
+        assert!(Config::load().unwrap().is_allowed(&project, "source env.sh\n"));
+
+        deny_command(Some(sub)).unwrap();
+        assert!(Config::load().unwrap().dir_info(&project).is_none());
+    }
+
+    #[test]
+    fn test_allow_refuses_unexpected_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        paths::use_test_dir(dir.path());
+        let project = fs::canonicalize(dir.path()).unwrap().join("app");
+        fs::create_dir_all(&project).unwrap();
+        fs::write(project.join("env.sh"), "export A=1\n").unwrap();
+        fs::write(project.join(".local_environment"), "source env.sh\n").unwrap();
+
+        let error = allow_command(Some(project.clone()), false, false, true, Some(Config::hash_content("other")))
+            .unwrap_err();
+        assert!(error.to_string().contains("Refusing to allow"), "{}", error);
+        assert!(Config::load().unwrap().dir_info(&project).is_none());
+    }
+}
@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files that decide what gets installed into a project's venv
+const MANIFESTS: &[&str] = &[
+    "pyproject.toml",
+    "setup.py",
+    "setup.cfg",
+    "poetry.lock",
+    "Pipfile",
+    "Pipfile.lock",
+    "uv.lock",
+    "pdm.lock",
+];
+
+/// Manifest hashes recorded when a venv was last created or synced
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct VenvRecord {
+    project: PathBuf,
+    /// File name -> SHA-256 of its contents
+    manifests: BTreeMap<String, String>,
+}
+
+impl VenvRecord {
+    fn new(project: &Path) -> Self {
+        Self {
+            project: project.to_path_buf(),
+            manifests: Manifests::fingerprint(project),
+        }
+    }
+}
+
+pub struct Manifests;
+
+impl Manifests {
+    /// Remember the project's current manifests as the ones the venv was built from
+    pub fn record(venv: &Path, project: &Path) -> Result<()> {
+        let path = Self::state_path()?;
+        let mut records = Self::load(&path);
+        records.insert(venv.display().to_string(), VenvRecord::new(project));
+        Self::save(&path, &records)
+    }
+
+    /// Manifests added, removed or modified since the venv was last synced.
+    /// A venv durrrrrenv never created or synced gets the current manifests
+    /// as its baseline, so it is only reported once they change.
+    pub fn changed(venv: &Path, project: &Path) -> Result<Vec<String>> {
+        let path = Self::state_path()?;
+        let mut records = Self::load(&path);
+        let current = Self::fingerprint(project);
+
+        let Some(record) = records.get(&venv.display().to_string()) else {
+            records.insert(venv.display().to_string(), VenvRecord::new(project));
+            Self::save(&path, &records)?;
+            return Ok(Vec::new());
+        };
+        let recorded = &record.manifests;
+
+        let mut changed: Vec<String> = recorded
+            .keys()
+            .chain(current.keys())
+            .filter(|name| recorded.get(*name) != current.get(*name))
+            .cloned()
+            .collect();
+        changed.sort();
+        changed.dedup();
+        Ok(changed)
+    }
+
+    /// Hashes of the manifests present in the project
+    fn fingerprint(project: &Path) -> BTreeMap<String, String> {
+        let mut names: Vec<String> = MANIFESTS.iter().map(|name| name.to_string()).collect();
+        if let Ok(entries) = fs::read_dir(project) {
+            names.extend(
+                entries
+                    .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                    .filter(|name| name.starts_with("requirements") && name.ends_with(".txt")),
+            );
+        }
+
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let contents = fs::read(project.join(&name)).ok()?;
+                Some((name, format!("{:x}", Sha256::digest(&contents))))
+            })
+            .collect()
+    }
+
+    /// Write through a temporary file so that a crash or a second shell
+    /// never leaves a half-written state file behind
+    fn save(path: &Path, records: &HashMap<String, VenvRecord>) -> Result<()> {
+        let json = serde_json::to_string_pretty(records).context("Failed to serialize venv state")?;
+        let temp = path.with_extension(format!("json.{}.tmp", std::process::id()));
+        fs::write(&temp, json).with_context(|| format!("Failed to write {}", temp.display()))?;
+        fs::rename(&temp, path).with_context(|| format!("Failed to replace {}", path.display()))
+    }
+
+    fn load(path: &Path) -> HashMap<String, VenvRecord> {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    fn state_path() -> Result<PathBuf> {
+        #[cfg(test)]
+        let state_dir = crate::paths::test_dir().join("state");
+        #[cfg(not(test))]
+        let state_dir = dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .context("Failed to determine state directory")?
+            .join("durrrrrenv");
+
+        fs::create_dir_all(&state_dir)
+            .context("Failed to create state directory")?;
+
+        Ok(state_dir.join("venvs.json"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_picks_up_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("requirements.txt"), "requests\n").unwrap();
+        fs::write(dir.path().join("requirements-dev.txt"), "pytest\n").unwrap();
+        fs::write(dir.path().join("README.md"), "hi\n").unwrap();
+
+        let before = Manifests::fingerprint(dir.path());
+        assert_eq!(
+            before.keys().collect::<Vec<_>>(),
+            vec!["requirements-dev.txt", "requirements.txt"]
+        );
+
+        fs::write(dir.path().join("requirements.txt"), "requests==2.31.0\n").unwrap();
+        let after = Manifests::fingerprint(dir.path());
+        assert_ne!(before["requirements.txt"], after["requirements.txt"]);
+        assert_eq!(before["requirements-dev.txt"], after["requirements-dev.txt"]);
+    }
+
+    #[test]
+    fn test_changed_reports_added_removed_and_modified() {
+        let dir = tempfile::tempdir().unwrap();
+        crate::paths::use_test_dir(dir.path());
+        let project = dir.path().join("app");
+        let venv = project.join(".venv");
+        fs::create_dir_all(&project).unwrap();
+        fs::write(project.join("requirements.txt"), "requests\n").unwrap();
+        fs::write(project.join("setup.py"), "setup()\n").unwrap();
+
+        // The first look only takes a baseline
+        assert!(Manifests::changed(&venv, &project).unwrap().is_empty());
+
+        fs::write(project.join("requirements.txt"), "requests==2.31.0\n").unwrap();
+        fs::remove_file(project.join("setup.py")).unwrap();
+        fs::write(project.join("uv.lock"), "").unwrap();
+        assert_eq!(
+            Manifests::changed(&venv, &project).unwrap(),
+            vec!["requirements.txt", "setup.py", "uv.lock"]
+        );
+
+        Manifests::record(&venv, &project).unwrap();
+        assert!(Manifests::changed(&venv, &project).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,444 @@
+use crate::executor::Executor;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::os::fd::AsFd;
+use std::path::{Path, PathBuf};
+use std::process::{Command as ProcessCommand, Stdio};
+
+/// Variables describing the build sandbox or the user's session rather than the shell
+const IGNORED_VARIABLES: &[&str] = &[
+    "HOME", "USER", "LOGNAME", "PWD", "OLDPWD", "SHELL", "SHLVL", "TERM", "TZ", "TMP", "TMPDIR", "TEMP",
+    "TEMPDIR", "NIX_BUILD_TOP", "NIX_BUILD_CORES", "NIX_LOG_FD", "NIX_STORE", "UID", "GID", "EUID", "_",
+    "__structuredAttrs",
+];
+
+/// Which Nix shell a directive loads
+#[derive(Debug, Clone, Copy)]
+pub enum Shell<'a> {
+    /// `nix print-dev-env` for a flake reference
+    Flake(&'a str),
+    /// `nix-shell` for a shell.nix or default.nix
+    NixShell(&'a str),
+}
+
+/// Remote flakes are re-evaluated after this many seconds, like Nix's own tarball-ttl
+const REMOTE_TTL_SECS: u64 = 3600;
+
+/// Bumped whenever the cached layout changes, which invalidates older caches
+const CACHE_VERSION: &str = "2";
+
+/// Written after nix-shell's environment, so a cut-off one isn't mistaken for complete
+const ENV_END_MARKER: &str = "DURRRRRENV_ENV_END";
+
+/// A Nix shell's environment as evaluated, independent of the environment
+/// it was evaluated from
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DevEnv {
+    /// Hash of the files the evaluation depended on
+    inputs: String,
+    /// When it was evaluated, in seconds since the epoch
+    #[serde(default)]
+    evaluated_at: u64,
+    pub variables: BTreeMap<String, String>,
+    /// The shell's PATH, in order
+    pub path: Vec<PathBuf>,
+}
+
+impl DevEnv {
+    /// What the shell adds or changes on top of the current environment:
+    /// PATH entries that aren't on PATH yet, and variables with another value
+    pub fn changes(&self) -> (Vec<&PathBuf>, Vec<(&String, &String)>) {
+        let current_path: Vec<PathBuf> = env::var_os("PATH")
+            .map(|path| env::split_paths(&path).collect())
+            .unwrap_or_default();
+
+        let path = self.path.iter().filter(|dir| !current_path.contains(dir)).collect();
+        let variables = self
+            .variables
+            .iter()
+            .filter(|(name, value)| env::var(name).ok().as_ref() != Some(*value))
+            .collect();
+        (path, variables)
+    }
+}
+
+pub struct Nix;
+
+impl Nix {
+    /// The shell's environment, evaluated once and then served from the
+    /// cache until flake.nix, flake.lock or the nix file change. Files these
+    /// import are not watched.
+    pub fn dev_env(shell: Shell, working_dir: &Path) -> Result<DevEnv> {
+        let program = match shell {
+            Shell::Flake(_) => "nix",
+            Shell::NixShell(_) => "nix-shell",
+        };
+        Self::load(shell, working_dir, &Self::cache_dir()?, Path::new(program))
+    }
+
+    /// Whether the shell's environment is cached and up to date
+    pub fn is_cached(shell: Shell, working_dir: &Path) -> bool {
+        let Ok(cache_dir) = Self::cache_dir() else {
+            return false;
+        };
+        Self::cached(shell, working_dir, &cache_dir).is_some()
+    }
+
+    /// The nix-shell file a `use_nix` line refers to: the given one, or
+    /// shell.nix, falling back to default.nix as nix-shell does
+    pub fn shell_file(file: Option<&str>, working_dir: &Path) -> String {
+        match file {
+            Some(file) => file.to_string(),
+            None if !working_dir.join("shell.nix").exists() && working_dir.join("default.nix").exists() => {
+                "default.nix".to_string()
+            }
+            None => "shell.nix".to_string(),
+        }
+    }
+
+    /// The directory of a local flake reference (`.`, `./sub#shell`,
+    /// `path:/abs?dir=sub`), or None for registry and remote references
+    pub fn flake_dir(reference: &str, working_dir: &Path) -> Option<PathBuf> {
+        let reference = reference.split('#').next().unwrap_or_default();
+        let (path, query) = reference.split_once('?').unwrap_or((reference, ""));
+        let path = path.strip_prefix("path:").unwrap_or(path);
+
+        if !(path.starts_with('/') || path == "." || path == ".." || path.starts_with("./") || path.starts_with("../")) {
+            return None;
+        }
+
+        let dir = working_dir.join(path);
+        Some(match query.split('&').find_map(|param| param.strip_prefix("dir=")) {
+            Some(sub) => dir.join(sub),
+            None => dir,
+        })
+    }
+
+    fn load(shell: Shell, working_dir: &Path, cache_dir: &Path, program: &Path) -> Result<DevEnv> {
+        if let Some(env) = Self::cached(shell, working_dir, cache_dir) {
+            return Ok(env);
+        }
+
+        fs::create_dir_all(cache_dir)
+            .with_context(|| format!("Failed to create {}", cache_dir.display()))?;
+        let mut env = Self::evaluate(shell, working_dir, cache_dir, program)?;
+        env.inputs = Self::inputs_hash(shell, working_dir);
+        env.evaluated_at = Self::now();
+
+        // A cache that can't be written only costs another evaluation next time.
+        // Written aside and renamed, so another shell never reads half a file.
+        let cache_file = Self::cache_file(shell, working_dir, cache_dir);
+        let temp_file = cache_file.with_extension(format!("json.{}.tmp", std::process::id()));
+        if let Ok(json) = serde_json::to_string(&env) {
+            if fs::write(&temp_file, json).and_then(|_| fs::rename(&temp_file, &cache_file)).is_err() {
+                let _ = fs::remove_file(&temp_file);
+            }
+        }
+
+        Ok(env)
+    }
+
+    /// The cached environment, unless its inputs changed, the store paths it
+    /// puts on PATH were garbage collected, or it is a remote flake's and expired
+    fn cached(shell: Shell, working_dir: &Path, cache_dir: &Path) -> Option<DevEnv> {
+        let contents = fs::read_to_string(Self::cache_file(shell, working_dir, cache_dir)).ok()?;
+        let env: DevEnv = serde_json::from_str(&contents).ok()?;
+
+        let expired = match shell {
+            Shell::Flake(reference) if Self::flake_dir(reference, working_dir).is_none() => {
+                Self::now().saturating_sub(env.evaluated_at) >= REMOTE_TTL_SECS
+            }
+            _ => false,
+        };
+        (env.inputs == Self::inputs_hash(shell, working_dir) && env.path.iter().all(|dir| dir.exists()) && !expired)
+            .then_some(env)
+    }
+
+    fn evaluate(shell: Shell, working_dir: &Path, cache_dir: &Path, program: &Path) -> Result<DevEnv> {
+        let mut command = ProcessCommand::new(program);
+        // nix-shell runs the shellHook, whose output would end up next to the
+        // environment on stdout. The environment goes to a file of its own
+        // instead, and stdout to the terminal with stderr.
+        let env_file = cache_dir.join(format!("env.{}.tmp", std::process::id()));
+        match shell {
+            Shell::Flake(reference) => {
+                command
+                    .args(["--extra-experimental-features", "nix-command flakes"])
+                    .args(["print-dev-env", "--json", reference])
+                    .stdout(Stdio::piped());
+            }
+            Shell::NixShell(file) => {
+                let target = Executor::shell_quote(&env_file.display().to_string());
+                let run = format!("env -0 > {} && printf '{}\\0' >> {}", target, ENV_END_MARKER, target);
+                command
+                    .args([file, "--run", &run])
+                    .stdout(io::stderr().as_fd().try_clone_to_owned()?);
+            }
+        }
+
+        // Nix reports progress on stderr, which goes to the terminal
+        let output = command
+            .current_dir(working_dir)
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()
+            .with_context(|| format!("Failed to run {}", program.display()));
+        let env_output = fs::read(&env_file);
+        let _ = fs::remove_file(&env_file);
+
+        let output = output?;
+        if !output.status.success() {
+            anyhow::bail!("{} exited with {}", program.display(), output.status);
+        }
+
+        let variables = match shell {
+            Shell::Flake(_) => Self::parse_dev_env_json(&String::from_utf8_lossy(&output.stdout))?,
+            Shell::NixShell(_) => {
+                let env_output = env_output.context("nix-shell did not write its environment")?;
+                Self::parse_env(&String::from_utf8_lossy(&env_output))?
+            }
+        };
+        Ok(Self::split_path(variables))
+    }
+
+    /// Exported string variables from `nix print-dev-env --json`
+    fn parse_dev_env_json(json: &str) -> Result<BTreeMap<String, String>> {
+        let dev_env: Value = serde_json::from_str(json).context("Failed to parse nix print-dev-env output")?;
+        let variables = dev_env["variables"]
+            .as_object()
+            .context("nix print-dev-env output has no variables")?;
+
+        Ok(variables
+            .iter()
+            .filter(|(_, variable)| variable["type"] == "exported")
+            .filter_map(|(name, variable)| Some((name.clone(), variable["value"].as_str()?.to_string())))
+            .collect())
+    }
+
+    /// NUL-separated NAME=value pairs from `env -0`, followed by the end marker
+    fn parse_env(output: &str) -> Result<BTreeMap<String, String>> {
+        let entries: Vec<&str> = output.split('\0').collect();
+        let end = entries
+            .iter()
+            .rposition(|entry| *entry == ENV_END_MARKER)
+            .context("nix-shell's environment was cut off")?;
+
+        Ok(entries[..end]
+            .iter()
+            .filter_map(|entry| entry.split_once('='))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect())
+    }
+
+    /// Separate PATH from the other variables and drop the ones describing
+    /// the build sandbox. Everything else is kept, so the cache doesn't depend
+    /// on the environment it was evaluated from.
+    fn split_path(mut variables: BTreeMap<String, String>) -> DevEnv {
+        let path = variables
+            .remove("PATH")
+            .map(|path| {
+                // Only directories that exist, so a cached one disappearing means
+                // its store path was garbage collected
+                env::split_paths(&path)
+                    .filter(|dir| dir.is_absolute() && dir.is_dir())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        variables.retain(|name, _| !IGNORED_VARIABLES.contains(&name.as_str()));
+
+        DevEnv {
+            variables,
+            path,
+            ..DevEnv::default()
+        }
+    }
+
+    /// The local files a shell is evaluated from: the nix-shell file, or a
+    /// local flake's flake.nix and flake.lock. Files these import are not included.
+    pub fn local_files(shell: Shell, working_dir: &Path) -> Vec<PathBuf> {
+        match shell {
+            Shell::Flake(reference) => match Self::flake_dir(reference, working_dir) {
+                Some(dir) => vec![dir.join("flake.nix"), dir.join("flake.lock")],
+                None => Vec::new(),
+            },
+            Shell::NixShell(file) => vec![working_dir.join(file)],
+        }
+    }
+
+    /// Hash of the directive and the files its evaluation reads. Remote
+    /// flakes have no local files; they expire instead.
+    fn inputs_hash(shell: Shell, working_dir: &Path) -> String {
+        let (kind, argument) = match shell {
+            Shell::Flake(reference) => ("flake", reference),
+            Shell::NixShell(file) => ("nix-shell", file),
+        };
+
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{}\0{}\0{}\0", CACHE_VERSION, kind, argument));
+        for file in Self::local_files(shell, working_dir) {
+            hasher.update(file.to_string_lossy().as_bytes());
+            hasher.update(fs::read(&file).unwrap_or_default());
+            hasher.update("\0");
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn now() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    /// One cache file per project and directive, replaced when the inputs change
+    fn cache_file(shell: Shell, working_dir: &Path, cache_dir: &Path) -> PathBuf {
+        let (kind, argument) = match shell {
+            Shell::Flake(reference) => ("flake", reference),
+            Shell::NixShell(file) => ("nix-shell", file),
+        };
+        let key = Sha256::digest(format!("{}\0{}\0{}", kind, argument, working_dir.display()));
+        cache_dir.join(format!("{:x}.json", key))
+    }
+
+    fn cache_dir() -> Result<PathBuf> {
+        Ok(dirs::cache_dir()
+            .context("Failed to determine cache directory")?
+            .join("durrrrrenv")
+            .join("nix"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_flake_env_is_cached_until_inputs_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        let cache = dir.path().join("cache");
+        let store_bin = dir.path().join("store/hello/bin");
+        fs::create_dir_all(&project).unwrap();
+        fs::create_dir_all(&store_bin).unwrap();
+        fs::write(project.join("flake.nix"), "{ }").unwrap();
+
+        // Stands in for nix, counting how often it is run
+        let runs = dir.path().join("runs");
+        let fake_nix = dir.path().join("nix");
+        let json = format!(
+            r#"{{"variables": {{"PATH": {{"type": "exported", "value": "{}:/path-not-set"}}, "HELLO": {{"type": "exported", "value": "world"}}, "TMPDIR": {{"type": "exported", "value": "/build"}}, "name": {{"type": "var", "value": "x"}}}}}}"#,
+            store_bin.display()
+        );
+        fs::write(&fake_nix, format!("#!/bin/sh\necho run >> '{}'\ncat <<'EOF'\n{}\nEOF\n", runs.display(), json)).unwrap();
+        fs::set_permissions(&fake_nix, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let load = || Nix::load(Shell::Flake("."), &project, &cache, &fake_nix).unwrap();
+        let run_count = || fs::read_to_string(&runs).unwrap().lines().count();
+
+        let env = load();
+        assert_eq!(env.variables, BTreeMap::from([("HELLO".to_string(), "world".to_string())]));
+        assert_eq!(env.path, vec![store_bin.clone()]);
+
+        load();
+        assert_eq!(run_count(), 1);
+
+        fs::write(project.join("flake.lock"), "{}").unwrap();
+        load();
+        assert_eq!(run_count(), 2);
+
+        // Store paths that were garbage collected make the cache stale
+        fs::remove_dir_all(&store_bin).unwrap();
+        load();
+        assert_eq!(run_count(), 3);
+    }
+
+    #[test]
+    fn test_nix_shell_env_is_cached_until_its_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        let cache = dir.path().join("cache");
+        let store_bin = dir.path().join("store/hello/bin");
+        fs::create_dir_all(&project).unwrap();
+        fs::create_dir_all(&store_bin).unwrap();
+        fs::write(project.join("shell.nix"), "{ }").unwrap();
+
+        // Stands in for nix-shell: runs the --run command in the shell's
+        // environment after a chatty shellHook, and counts its runs
+        let runs = dir.path().join("runs");
+        let fake_nix_shell = dir.path().join("nix-shell");
+        std::os::unix::fs::symlink("/usr/bin/env", store_bin.join("env")).unwrap();
+        fs::write(
+            &fake_nix_shell,
+            format!(
+                "#!/bin/sh\necho \"$1\" >> '{}'\necho 'HOOK=printed by shellHook'\n\
+                 exec /usr/bin/env -i PATH={}:/nonexistent/bin HELLO=world HOME=/homeless-shelter /bin/sh -c \"$3\"\n",
+                runs.display(),
+                store_bin.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&fake_nix_shell, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let load = || Nix::load(Shell::NixShell("shell.nix"), &project, &cache, &fake_nix_shell).unwrap();
+
+        let env = load();
+        assert_eq!(env.variables, BTreeMap::from([("HELLO".to_string(), "world".to_string())]));
+        assert_eq!(env.path, vec![store_bin.clone()]);
+        // Only the cache itself is left behind
+        let names: Vec<String> = fs::read_dir(&cache)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert!(names.len() == 1 && names[0].ends_with(".json"), "{:?}", names);
+
+        load();
+        assert_eq!(fs::read_to_string(&runs).unwrap(), "shell.nix\n");
+
+        fs::write(project.join("shell.nix"), "{ pkgs ? import <nixpkgs> {} }: { }").unwrap();
+        load();
+        assert_eq!(fs::read_to_string(&runs).unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn test_changes_are_relative_to_current_environment() {
+        let current_dir = env::split_paths(&env::var_os("PATH").unwrap()).next().unwrap();
+        let (name, value) = env::vars().next().unwrap();
+        let env = DevEnv {
+            variables: BTreeMap::from([(name, value), ("DURRRRRENV_TEST_NEW".to_string(), "1".to_string())]),
+            path: vec![PathBuf::from("/nix/store/hello/bin"), current_dir],
+            ..DevEnv::default()
+        };
+
+        let (path, variables) = env.changes();
+        assert_eq!(path, vec![&PathBuf::from("/nix/store/hello/bin")]);
+        assert_eq!(variables, vec![(&"DURRRRRENV_TEST_NEW".to_string(), &"1".to_string())]);
+    }
+
+    #[test]
+    fn test_flake_dir() {
+        let project = Path::new("/srv/app");
+        assert_eq!(Nix::flake_dir(".", project), Some(PathBuf::from("/srv/app/.")));
+        assert_eq!(Nix::flake_dir("./sub#dev", project), Some(PathBuf::from("/srv/app/./sub")));
+        assert_eq!(Nix::flake_dir("path:/opt/flake?dir=x", project), Some(PathBuf::from("/opt/flake/x")));
+        assert_eq!(Nix::flake_dir("github:org/repo#dev", project), None);
+        assert_eq!(Nix::flake_dir("nixpkgs", project), None);
+    }
+
+    #[test]
+    fn test_parse_env_output() {
+        let variables = Nix::parse_env("A=1\0MULTI=line one\nline two\0EMPTY=\0DURRRRRENV_ENV_END\0").unwrap();
+        assert_eq!(variables["MULTI"], "line one\nline two");
+        assert_eq!(variables["EMPTY"], "");
+        assert!(!variables.contains_key("DURRRRRENV_ENV_END"));
+
+        assert!(Nix::parse_env("A=1\0MULTI=line one").is_err());
+    }
+}
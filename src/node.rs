@@ -0,0 +1,264 @@
+use anyhow::{Context, Result};
+use semver::{Version, VersionReq};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Files naming the project's Node version, in order of preference
+const VERSION_FILES: &[&str] = &[".nvmrc", ".node-version"];
+
+/// LTS codenames usable as `lts/<name>` and the major version each stands for
+const LTS_CODENAMES: &[(&str, u64)] = &[
+    ("argon", 4),
+    ("boron", 6),
+    ("carbon", 8),
+    ("dubnium", 10),
+    ("erbium", 12),
+    ("fermium", 14),
+    ("gallium", 16),
+    ("hydrogen", 18),
+    ("iron", 20),
+    ("jod", 22),
+    ("krypton", 24),
+];
+
+/// An installed Node version
+#[derive(Debug, Clone)]
+pub struct Installation {
+    pub version: Version,
+    /// Directory containing the node binary
+    pub bin: PathBuf,
+    /// Version manager that installed it
+    pub manager: &'static str,
+}
+
+/// Which versions a version spec accepts
+#[derive(Debug)]
+enum Matcher {
+    /// `node`, `stable`, `latest`: the newest installed version
+    Any,
+    /// `lts/*` (any LTS major) or `lts/<codename>`
+    Lts(Option<u64>),
+    /// Semver ranges, any of which may match (`||`)
+    Ranges(Vec<VersionReq>),
+}
+
+pub struct Node;
+
+impl Node {
+    /// Find the installation for `use_node [version]`. Without a version the
+    /// project's .nvmrc or .node-version decides.
+    pub fn resolve(version: Option<&str>, working_dir: &Path) -> Result<Installation> {
+        let (spec, origin) = match version {
+            Some(version) => (version.to_string(), "use_node".to_string()),
+            None => Self::version_file(working_dir)?,
+        };
+
+        Self::select(&spec, &Self::installations())
+            .with_context(|| format!("Cannot use Node '{}' (from {})", spec, origin))
+    }
+
+    /// Read the version from the first version file next to the env file
+    fn version_file(working_dir: &Path) -> Result<(String, String)> {
+        for name in VERSION_FILES {
+            let Ok(contents) = fs::read_to_string(working_dir.join(name)) else {
+                continue;
+            };
+            let spec = contents
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty() && !line.starts_with('#'))
+                .with_context(|| format!("{} is empty", name))?;
+            return Ok((spec.to_string(), name.to_string()));
+        }
+
+        anyhow::bail!(
+            "use_node needs a version: none given and no {} in {}",
+            VERSION_FILES.join(" or "),
+            working_dir.display()
+        )
+    }
+
+    /// Pick the newest installation the spec accepts
+    fn select(spec: &str, installations: &[Installation]) -> Result<Installation> {
+        let matcher = Self::parse_spec(spec)?;
+
+        if let Some(best) = installations
+            .iter()
+            .filter(|installation| matcher.matches(&installation.version))
+            .max_by(|a, b| a.version.cmp(&b.version))
+        {
+            return Ok(best.clone());
+        }
+
+        if installations.is_empty() {
+            anyhow::bail!("no Node installations found under nvm, fnm or volta");
+        }
+
+        let mut sorted: Vec<&Installation> = installations.iter().collect();
+        sorted.sort_by(|a, b| a.version.cmp(&b.version));
+        let installed: Vec<String> = sorted
+            .iter()
+            .map(|installation| format!("{} ({})", installation.version, installation.manager))
+            .collect();
+        anyhow::bail!("no installed version matches. Installed: {}", installed.join(", "))
+    }
+
+    /// Understand the version formats nvm accepts: `20`, `v20.11.0`,
+    /// `20.x`, `>=18 <21`, `lts/iron`, `lts/*`, `node`
+    fn parse_spec(spec: &str) -> Result<Matcher> {
+        let spec = spec.trim().to_lowercase();
+
+        if matches!(spec.as_str(), "node" | "stable" | "latest" | "current" | "*") {
+            return Ok(Matcher::Any);
+        }
+
+        if spec == "lts" || spec == "lts/*" {
+            return Ok(Matcher::Lts(None));
+        }
+        if let Some(codename) = spec.strip_prefix("lts/") {
+            let major = LTS_CODENAMES
+                .iter()
+                .find(|(name, _)| *name == codename)
+                .map(|(_, major)| *major)
+                .with_context(|| format!("Unknown LTS codename '{}'", codename))?;
+            return Ok(Matcher::Lts(Some(major)));
+        }
+
+        let ranges = spec
+            .split("||")
+            .map(|range| Self::parse_range(range.trim()))
+            .collect::<Result<_>>()?;
+        Ok(Matcher::Ranges(ranges))
+    }
+
+    fn parse_range(range: &str) -> Result<VersionReq> {
+        let bare = range.strip_prefix('v').unwrap_or(range);
+
+        // A plain version is exact when complete and a prefix otherwise:
+        // 20.11.0 means exactly that, 20.11 means any 20.11.x
+        let is_plain = !bare.is_empty() && bare.split('.').all(|part| part.parse::<u64>().is_ok());
+        let requirement = if is_plain {
+            match bare.split('.').count() {
+                3 => format!("={}", bare),
+                1 | 2 => format!("~{}", bare),
+                _ => anyhow::bail!("Invalid Node version '{}'", range),
+            }
+        } else {
+            // npm separates comparators with spaces, the semver crate with commas
+            range.split_whitespace().collect::<Vec<_>>().join(", ")
+        };
+
+        VersionReq::parse(&requirement).with_context(|| format!("Invalid Node version '{}'", range))
+    }
+
+    /// Every Node installed by nvm, fnm or volta
+    fn installations() -> Vec<Installation> {
+        let home = dirs::home_dir().unwrap_or_default();
+        let root = |var: &str, default: PathBuf| env::var_os(var).map(PathBuf::from).unwrap_or(default);
+
+        let fnm_default = dirs::data_dir()
+            .map(|dir| dir.join("fnm"))
+            .filter(|dir| dir.exists())
+            .unwrap_or_else(|| home.join(".fnm"));
+
+        let mut installations = Vec::new();
+        installations.extend(Self::scan(
+            "nvm",
+            &root("NVM_DIR", home.join(".nvm")).join("versions/node"),
+            "bin",
+        ));
+        installations.extend(Self::scan(
+            "fnm",
+            &root("FNM_DIR", fnm_default).join("node-versions"),
+            "installation/bin",
+        ));
+        installations.extend(Self::scan(
+            "volta",
+            &root("VOLTA_HOME", home.join(".volta")).join("tools/image/node"),
+            "bin",
+        ));
+        installations
+    }
+
+    /// Versions installed as `<dir>/<version>/<bin>/node`
+    fn scan(manager: &'static str, dir: &Path, bin: &str) -> Vec<Installation> {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+
+        entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let name = entry.file_name();
+                let name = name.to_str()?;
+                let version = Version::parse(name.strip_prefix('v').unwrap_or(name)).ok()?;
+                let bin = entry.path().join(bin);
+
+                bin.join("node").exists().then_some(Installation {
+                    version,
+                    bin,
+                    manager,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Matcher {
+    fn matches(&self, version: &Version) -> bool {
+        match self {
+            Matcher::Any => true,
+            Matcher::Lts(Some(major)) => version.major == *major,
+            Matcher::Lts(None) => version.major >= 4 && version.major.is_multiple_of(2),
+            Matcher::Ranges(ranges) => ranges.iter().any(|range| range.matches(version)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn installed(versions: &[&str]) -> Vec<Installation> {
+        versions
+            .iter()
+            .map(|version| Installation {
+                version: Version::parse(version).unwrap(),
+                bin: PathBuf::from(format!("/nvm/v{}/bin", version)),
+                manager: "nvm",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_select_matching_version() {
+        let installations = installed(&["18.19.0", "20.9.0", "20.11.1", "21.6.0"]);
+        let pick = |spec| Node::select(spec, &installations).unwrap().version.to_string();
+
+        assert_eq!(pick("20"), "20.11.1");
+        assert_eq!(pick("v20.9.0"), "20.9.0");
+        assert_eq!(pick("20.x"), "20.11.1");
+        assert_eq!(pick(">=18 <20"), "18.19.0");
+        assert_eq!(pick("16 || 18"), "18.19.0");
+        assert_eq!(pick("lts/*"), "20.11.1");
+        assert_eq!(pick("lts/hydrogen"), "18.19.0");
+        assert_eq!(pick("node"), "21.6.0");
+
+        let error = format!("{:#}", Node::select("22", &installations).unwrap_err());
+        assert!(error.contains("Installed: 18.19.0 (nvm), 20.9.0 (nvm), 20.11.1 (nvm), 21.6.0 (nvm)"));
+    }
+
+    #[test]
+    fn test_scan_layouts() {
+        let dir = tempfile::tempdir().unwrap();
+        let fnm = dir.path().join("node-versions");
+        fs::create_dir_all(fnm.join("v20.11.1/installation/bin")).unwrap();
+        fs::write(fnm.join("v20.11.1/installation/bin/node"), "").unwrap();
+        fs::create_dir_all(fnm.join(".downloads")).unwrap();
+
+        let found = Node::scan("fnm", &fnm, "installation/bin");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].bin, fnm.join("v20.11.1/installation/bin"));
+    }
+}
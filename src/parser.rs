@@ -1,13 +1,89 @@
 use anyhow::{Context, Result};
+use std::fmt;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Command {
     /// Source a file: source <file>
     Source { path: String },
-    /// Activate Python venv: python_venv [path]
-    PythonVenv { path: String },
+    /// Activate Python venv: python_venv [path] [--create] [--python <version>]
+    PythonVenv {
+        path: String,
+        /// Offer to create the venv when it doesn't exist
+        create: bool,
+        /// Python version to create it with, instead of .python-version
+        python: Option<String>,
+    },
     /// Process substitution: source <(command)
     ProcessSubstitution { command: String },
+    /// Put a Node installation on PATH: use_node [version]
+    UseNode { version: Option<String> },
+    /// Pin the Rust toolchain: use_rust [toolchain]
+    UseRust { toolchain: Option<String> },
+    /// Select a JDK: use_jdk <version>
+    UseJdk { version: String },
+    /// Select a Ruby with project-scoped gems: use_ruby [version]
+    UseRuby { version: Option<String> },
+    /// Activate a conda/mamba environment: conda_env <name|path>
+    CondaEnv { env: String },
+    /// Load a nix-shell environment: use_nix [file]
+    UseNix { file: Option<String> },
+    /// Load a flake's dev shell: use_flake [reference]
+    UseFlake { reference: Option<String> },
+    /// Put the asdf/mise tools from .tool-versions on PATH: use_tool_versions [file]
+    UseToolVersions { file: Option<String> },
+}
+
+impl Command {
+    /// Name of the directive as written in .local_environment
+    pub fn directive_name(&self) -> &'static str {
+        match self {
+            Command::Source { .. } => "source",
+            Command::PythonVenv { .. } => "python_venv",
+            Command::ProcessSubstitution { .. } => "process_substitution",
+            Command::UseNode { .. } => "use_node",
+            Command::UseRust { .. } => "use_rust",
+            Command::UseJdk { .. } => "use_jdk",
+            Command::UseRuby { .. } => "use_ruby",
+            Command::CondaEnv { .. } => "conda_env",
+            Command::UseNix { .. } => "use_nix",
+            Command::UseFlake { .. } => "use_flake",
+            Command::UseToolVersions { .. } => "use_tool_versions",
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    /// Render the command the way it is written in .local_environment
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Source { path } => write!(f, "source {}", path),
+            Command::PythonVenv { path, create, python } => {
+                write!(f, "python_venv {}", path)?;
+                if *create {
+                    write!(f, " --create")?;
+                }
+                if let Some(python) = python {
+                    write!(f, " --python {}", python)?;
+                }
+                Ok(())
+            }
+            Command::ProcessSubstitution { command } => write!(f, "source <({})", command),
+            Command::UseNode { version: None } => write!(f, "use_node"),
+            Command::UseNode { version: Some(version) } => write!(f, "use_node {}", version),
+            Command::UseRust { toolchain: None } => write!(f, "use_rust"),
+            Command::UseRust { toolchain: Some(toolchain) } => write!(f, "use_rust {}", toolchain),
+            Command::UseJdk { version } => write!(f, "use_jdk {}", version),
+            Command::UseRuby { version: None } => write!(f, "use_ruby"),
+            Command::UseRuby { version: Some(version) } => write!(f, "use_ruby {}", version),
+            Command::CondaEnv { env } => write!(f, "conda_env {}", env),
+            Command::UseNix { file: None } => write!(f, "use_nix"),
+            Command::UseNix { file: Some(file) } => write!(f, "use_nix {}", file),
+            Command::UseFlake { reference: None } => write!(f, "use_flake"),
+            Command::UseFlake { reference: Some(reference) } => write!(f, "use_flake {}", reference),
+            Command::UseToolVersions { file: None } => write!(f, "use_tool_versions"),
+            Command::UseToolVersions { file: Some(file) } => write!(f, "use_tool_versions {}", file),
+        }
+    }
 }
 
 pub struct Parser;
@@ -51,6 +127,46 @@ impl Parser {
             return Self::parse_python_venv(line);
         }
 
+        // Check for use_node
+        if line.starts_with("use_node") {
+            return Self::parse_use_node(line);
+        }
+
+        // Check for use_rust
+        if line.starts_with("use_rust") {
+            return Self::parse_use_rust(line);
+        }
+
+        // Check for use_jdk
+        if line.starts_with("use_jdk") {
+            return Self::parse_use_jdk(line);
+        }
+
+        // Check for use_ruby
+        if line.starts_with("use_ruby") {
+            return Self::parse_use_ruby(line);
+        }
+
+        // Check for conda_env
+        if line.starts_with("conda_env") {
+            return Self::parse_conda_env(line);
+        }
+
+        // Check for use_nix
+        if line.starts_with("use_nix") {
+            return Self::parse_use_nix(line);
+        }
+
+        // Check for use_flake
+        if line.starts_with("use_flake") {
+            return Self::parse_use_flake(line);
+        }
+
+        // Check for use_tool_versions
+        if line.starts_with("use_tool_versions") {
+            return Self::parse_use_tool_versions(line);
+        }
+
         anyhow::bail!("Unknown command: {}", line)
     }
 
@@ -69,17 +185,137 @@ impl Parser {
 
     /// Parse: python_venv [path]
     fn parse_python_venv(line: &str) -> Result<Command> {
+        let mut parts = line.split_whitespace().skip(1);
+        let mut path = None;
+        let mut create = false;
+        let mut python = None;
+
+        while let Some(part) = parts.next() {
+            match part {
+                "--create" => create = true,
+                "--python" => {
+                    let version = parts.next().context("--python expects a version")?;
+                    python = Some(version.to_string());
+                }
+                flag if flag.starts_with("--") => anyhow::bail!("Unknown python_venv option: {}", flag),
+                _ if path.is_some() => anyhow::bail!("python_venv command expects zero or one path"),
+                _ => path = Some(part.to_string()),
+            }
+        }
+
+        if python.is_some() && !create {
+            anyhow::bail!("--python only applies together with --create");
+        }
+
+        Ok(Command::PythonVenv {
+            path: path.unwrap_or_else(|| ".venv".to_string()),
+            create,
+            python,
+        })
+    }
+
+    /// Parse: use_node [version]
+    fn parse_use_node(line: &str) -> Result<Command> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+
+        if parts[0] != "use_node" || parts.len() > 2 {
+            anyhow::bail!("use_node command expects zero or one argument");
+        }
+
+        Ok(Command::UseNode {
+            version: parts.get(1).map(|version| version.to_string()),
+        })
+    }
+
+    /// Parse: use_rust [toolchain]
+    fn parse_use_rust(line: &str) -> Result<Command> {
         let parts: Vec<&str> = line.split_whitespace().collect();
 
-        let path = if parts.len() == 1 {
-            ".venv".to_string()
-        } else if parts.len() == 2 {
-            parts[1].to_string()
-        } else {
-            anyhow::bail!("python_venv command expects zero or one argument");
-        };
+        if parts[0] != "use_rust" || parts.len() > 2 {
+            anyhow::bail!("use_rust command expects zero or one argument");
+        }
 
-        Ok(Command::PythonVenv { path })
+        Ok(Command::UseRust {
+            toolchain: parts.get(1).map(|toolchain| toolchain.to_string()),
+        })
+    }
+
+    /// Parse: use_jdk <version>
+    fn parse_use_jdk(line: &str) -> Result<Command> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+
+        if parts[0] != "use_jdk" || parts.len() != 2 {
+            anyhow::bail!("use_jdk command expects exactly one argument");
+        }
+
+        Ok(Command::UseJdk {
+            version: parts[1].to_string(),
+        })
+    }
+
+    /// Parse: use_ruby [version]
+    fn parse_use_ruby(line: &str) -> Result<Command> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+
+        if parts[0] != "use_ruby" || parts.len() > 2 {
+            anyhow::bail!("use_ruby command expects zero or one argument");
+        }
+
+        Ok(Command::UseRuby {
+            version: parts.get(1).map(|version| version.to_string()),
+        })
+    }
+
+    /// Parse: conda_env <name|path>
+    fn parse_conda_env(line: &str) -> Result<Command> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+
+        if parts[0] != "conda_env" || parts.len() != 2 {
+            anyhow::bail!("conda_env command expects exactly one argument");
+        }
+
+        Ok(Command::CondaEnv {
+            env: parts[1].to_string(),
+        })
+    }
+
+    /// Parse: use_nix [file]
+    fn parse_use_nix(line: &str) -> Result<Command> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+
+        if parts[0] != "use_nix" || parts.len() > 2 {
+            anyhow::bail!("use_nix command expects zero or one argument");
+        }
+
+        Ok(Command::UseNix {
+            file: parts.get(1).map(|file| file.to_string()),
+        })
+    }
+
+    /// Parse: use_flake [reference]
+    fn parse_use_flake(line: &str) -> Result<Command> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+
+        if parts[0] != "use_flake" || parts.len() > 2 {
+            anyhow::bail!("use_flake command expects zero or one argument");
+        }
+
+        Ok(Command::UseFlake {
+            reference: parts.get(1).map(|reference| reference.to_string()),
+        })
+    }
+
+    /// Parse: use_tool_versions [file]
+    fn parse_use_tool_versions(line: &str) -> Result<Command> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+
+        if parts[0] != "use_tool_versions" || parts.len() > 2 {
+            anyhow::bail!("use_tool_versions command expects zero or one argument");
+        }
+
+        Ok(Command::UseToolVersions {
+            file: parts.get(1).map(|file| file.to_string()),
+        })
     }
 
     /// Parse: source <(command args...)
@@ -123,7 +359,9 @@ mod tests {
         assert_eq!(
             cmd,
             Command::PythonVenv {
-                path: ".venv".to_string()
+                path: ".venv".to_string(),
+                create: false,
+                python: None,
             }
         );
     }
@@ -134,11 +372,28 @@ mod tests {
         assert_eq!(
             cmd,
             Command::PythonVenv {
-                path: "venv".to_string()
+                path: "venv".to_string(),
+                create: false,
+                python: None,
             }
         );
     }
 
+    #[test]
+    fn test_parse_python_venv_create() {
+        let cmd = Parser::parse_line("python_venv --create --python 3.11").unwrap();
+        assert_eq!(
+            cmd,
+            Command::PythonVenv {
+                path: ".venv".to_string(),
+                create: true,
+                python: Some("3.11".to_string()),
+            }
+        );
+        assert!(Parser::parse_line("python_venv --python 3.11").is_err());
+        assert!(Parser::parse_line("python_venv venv other").is_err());
+    }
+
     #[test]
     fn test_parse_process_substitution() {
         let cmd = Parser::parse_line("source <(west completion zsh)").unwrap();
@@ -150,6 +405,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_display_roundtrip() {
+        for line in [
+            "source ~/.bashrc",
+            "python_venv venv",
+            "python_venv auto --create --python 3.11",
+            "source <(west completion zsh)",
+            "use_node",
+            "use_node 20",
+            "use_rust",
+            "use_rust nightly-2024-01-01",
+            "use_jdk 17",
+            "use_ruby",
+            "use_ruby 3.2",
+            "conda_env ml",
+            "use_nix",
+            "use_nix nix/shell.nix",
+            "use_flake",
+            "use_flake .#ci",
+            "use_tool_versions",
+            "use_tool_versions tools/.tool-versions",
+        ] {
+            let cmd = Parser::parse_line(line).unwrap();
+            assert_eq!(cmd.to_string(), line);
+            assert_eq!(Parser::parse_line(&cmd.to_string()).unwrap(), cmd);
+        }
+    }
+
     #[test]
     fn test_parse_multi_line() {
         let content = r#"
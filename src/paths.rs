@@ -0,0 +1,114 @@
+//! Trust keys use canonical paths; everything the hook compares against `$PWD`
+//! uses logical paths (symlinks preserved). Both forms are emitted to the hook.
+
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+
+/// Current directory as the shell sees it.
+///
+/// `$PWD` keeps symlinks intact, while `getcwd` resolves them. `$PWD` is only
+/// trusted when it still refers to the same directory as `getcwd`.
+pub fn logical_current_dir() -> Result<PathBuf> {
+    let physical = env::current_dir().context("Failed to get current directory")?;
+
+    if let Some(pwd) = env::var_os("PWD").map(PathBuf::from) {
+        if pwd.is_absolute() && same_file(&pwd, &physical) {
+            return Ok(pwd);
+        }
+    }
+
+    Ok(physical)
+}
+
+/// Turn a user-supplied path into an absolute logical path without resolving
+/// symlinks. `..` is applied lexically, as the shell does for `cd`.
+pub fn absolute_logical(path: &Path) -> Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(normalize(path))
+    } else {
+        Ok(normalize(&logical_current_dir()?.join(path)))
+    }
+}
+
+/// Remove `.` and `..` components lexically, for paths that don't exist yet
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+
+    normalized
+}
+
+/// Canonical form of a path, or the path itself if it cannot be resolved
+pub fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    static TEST_DIR: std::cell::RefCell<Option<PathBuf>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Tests keep config and state in a directory of their own instead of the user's
+#[cfg(test)]
+pub fn use_test_dir(dir: &Path) {
+    TEST_DIR.with(|test_dir| *test_dir.borrow_mut() = Some(dir.to_path_buf()));
+}
+
+/// Stands in for the config and state directories in tests
+#[cfg(test)]
+pub fn test_dir() -> PathBuf {
+    TEST_DIR
+        .with(|test_dir| test_dir.borrow().clone())
+        .expect("tests that write config or state must call paths::use_test_dir first")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_canonical_resolves_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("real");
+        let link = dir.path().join("link");
+        fs::create_dir(&real).unwrap();
+        symlink(&real, &link).unwrap();
+
+        assert_eq!(canonical(&link), canonical(&real));
+        assert!(same_file(&link, &real));
+    }
+
+    #[test]
+    fn test_absolute_logical_normalizes_parents() {
+        assert_eq!(
+            absolute_logical(Path::new("/home/user/project/../other/./x")).unwrap(),
+            Path::new("/home/user/other/x")
+        );
+    }
+
+    #[test]
+    fn test_canonical_keeps_missing_paths() {
+        let path = Path::new("/does/not/exist/anywhere");
+        assert_eq!(canonical(path), path);
+    }
+}
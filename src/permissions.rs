@@ -0,0 +1,133 @@
+use crate::settings::PermissionSettings;
+use anyhow::{Context, Result};
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+const WORLD_WRITABLE: u32 = 0o002;
+const GROUP_WRITABLE: u32 = 0o020;
+const STICKY: u32 = 0o1000;
+
+pub struct Permissions;
+
+impl Permissions {
+    /// Verify that only the current user or root can modify a file.
+    ///
+    /// The file itself and every directory above it are checked, since write
+    /// access to any of them is enough to swap the file out.
+    pub fn verify(path: &Path, settings: &PermissionSettings) -> Result<()> {
+        let canonical = fs::canonicalize(path)
+            .with_context(|| format!("Failed to resolve {}", path.display()))?;
+        let current_uid = unsafe { libc::geteuid() };
+
+        for component in canonical.ancestors() {
+            // Only paths inside a shared directory are exempt, not the ones above it
+            let shared = settings.is_shared(component);
+            let metadata = fs::metadata(component)
+                .with_context(|| format!("Failed to inspect {}", component.display()))?;
+            let mode = metadata.mode();
+
+            if metadata.uid() != current_uid && metadata.uid() != 0 && !shared {
+                anyhow::bail!(
+                    "{} is owned by uid {}, not by you or root",
+                    component.display(),
+                    metadata.uid()
+                );
+            }
+
+            // Sticky directories (like /tmp) only let owners rename or remove entries
+            if metadata.is_dir() && mode & STICKY != 0 {
+                continue;
+            }
+
+            if mode & WORLD_WRITABLE != 0 {
+                anyhow::bail!(
+                    "{} is world-writable (mode {:04o})",
+                    component.display(),
+                    mode & 0o7777
+                );
+            }
+
+            if mode & GROUP_WRITABLE != 0 && !shared {
+                anyhow::bail!(
+                    "{} is group-writable (mode {:04o}); add its directory to \
+                     permissions.shared_dirs in config.toml if this is intended",
+                    component.display(),
+                    mode & 0o7777
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn set_mode(path: &Path, mode: u32) {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn test_private_file_is_accepted() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join(".local_environment");
+        fs::write(&file, "python_venv\n").unwrap();
+        set_mode(dir.path(), 0o755);
+        set_mode(&file, 0o644);
+
+        assert!(Permissions::verify(&file, &PermissionSettings::default()).is_ok());
+    }
+
+    #[test]
+    fn test_world_writable_file_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join(".local_environment");
+        fs::write(&file, "python_venv\n").unwrap();
+        set_mode(dir.path(), 0o755);
+        set_mode(&file, 0o666);
+
+        let err = Permissions::verify(&file, &PermissionSettings::default()).unwrap_err();
+        assert!(err.to_string().contains("world-writable"));
+    }
+
+    #[test]
+    fn test_group_writable_directory_requires_shared_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join(".local_environment");
+        fs::write(&file, "python_venv\n").unwrap();
+        set_mode(dir.path(), 0o775);
+        set_mode(&file, 0o644);
+
+        assert!(Permissions::verify(&file, &PermissionSettings::default()).is_err());
+
+        let settings = PermissionSettings {
+            shared_dirs: vec![dir.path().to_path_buf()],
+        };
+        assert!(Permissions::verify(&file, &settings).is_ok());
+    }
+
+    #[test]
+    fn test_shared_dir_does_not_exempt_its_parents() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared = dir.path().join("shared");
+        fs::create_dir(&shared).unwrap();
+        let file = shared.join(".local_environment");
+        fs::write(&file, "python_venv\n").unwrap();
+        set_mode(dir.path(), 0o775);
+        set_mode(&shared, 0o775);
+        set_mode(&file, 0o644);
+
+        let settings = PermissionSettings {
+            shared_dirs: vec![shared.clone()],
+        };
+        let err = Permissions::verify(&file, &settings).unwrap_err();
+        assert!(err.to_string().contains(&format!("{} is group-writable", dir.path().canonicalize().unwrap().display())));
+
+        set_mode(dir.path(), 0o755);
+        assert!(Permissions::verify(&file, &settings).is_ok());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::config::PinnedBinary;
+use crate::executor::Executor;
+use crate::parser::Command;
+use crate::settings::Settings;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+pub struct Pinning;
+
+impl Pinning {
+    /// Resolve the program of every process substitution to an absolute path,
+    /// on the PATH the directives before it set up. A program that is not
+    /// found is pinned as missing, so it is reported if it shows up later.
+    pub fn pin(commands: &[Command], working_dir: &Path, settings: &Settings) -> Result<Vec<PinnedBinary>> {
+        let mut pins = Vec::new();
+
+        for (program, path) in Self::resolve_programs(commands, working_dir, settings) {
+            let sha256 = match &path {
+                Some(path) if settings.pinning.hash => Some(Self::hash_file(path)?),
+                _ => None,
+            };
+
+            pins.push(PinnedBinary { program, path, sha256 });
+        }
+
+        Ok(pins)
+    }
+
+    /// Compare pinned binaries against what the programs resolve to now.
+    /// Returns a description of each difference.
+    pub fn differences(
+        pins: &[PinnedBinary],
+        commands: &[Command],
+        working_dir: &Path,
+        settings: &Settings,
+    ) -> Vec<String> {
+        let mut differences = Vec::new();
+        let mut unmatched: Vec<&PinnedBinary> = pins.iter().collect();
+
+        for (program, path) in Self::resolve_programs(commands, working_dir, settings) {
+            // A program used twice is matched against its pins in order
+            let Some(index) = unmatched.iter().position(|pin| pin.program == program) else {
+                differences.push(format!("`{}` was not pinned when the file was allowed", program));
+                continue;
+            };
+            let pin = unmatched.remove(index);
+
+            match (&pin.path, path) {
+                (None, None) => {}
+                (None, Some(path)) => differences.push(format!(
+                    "`{}` now resolves to {} (not found when approved)",
+                    program,
+                    path.display()
+                )),
+                (Some(approved), None) => differences.push(format!(
+                    "`{}` no longer resolves (approved {})",
+                    program,
+                    approved.display()
+                )),
+                (Some(approved), Some(path)) if path != *approved => differences.push(format!(
+                    "`{}` now resolves to {} (approved {})",
+                    program,
+                    path.display(),
+                    approved.display()
+                )),
+                (Some(_), Some(path)) => {
+                    if let Some(expected) = &pin.sha256 {
+                        match Self::hash_file(&path) {
+                            Ok(actual) if actual == *expected => {}
+                            _ => differences.push(format!(
+                                "{} has changed since it was approved",
+                                path.display()
+                            )),
+                        }
+                    }
+                }
+            }
+        }
+
+        differences
+    }
+
+    /// The program of each process substitution and where it resolves
+    fn resolve_programs(
+        commands: &[Command],
+        working_dir: &Path,
+        settings: &Settings,
+    ) -> Vec<(String, Option<PathBuf>)> {
+        Executor::substitution_environments(commands, working_dir, settings)
+            .into_iter()
+            .filter_map(|(command, environment)| {
+                let program = Self::program(&command)?;
+                Some((program.to_string(), Self::resolve(program, working_dir, environment.path())))
+            })
+            .collect()
+    }
+
+    /// First word of a command line
+    pub fn program(command: &str) -> Option<&str> {
+        command.split_whitespace().next()
+    }
+
+    /// Find a program the way the shell would: paths are taken as-is (relative
+    /// to the project), bare names are looked up on `path`
+    pub fn resolve(program: &str, working_dir: &Path, path: Option<&str>) -> Option<PathBuf> {
+        if program.contains('/') {
+            let path = working_dir.join(program);
+            return Self::is_executable(&path).then(|| fs::canonicalize(&path).unwrap_or(path));
+        }
+
+        env::split_paths(path?)
+            .map(|dir| dir.join(program))
+            .find(|candidate| Self::is_executable(candidate))
+    }
+
+    fn is_executable(path: &Path) -> bool {
+        fs::metadata(path)
+            .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    }
+
+    fn hash_file(path: &Path) -> Result<String> {
+        let mut file = fs::File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut file, &mut hasher)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn write_executable(path: &Path, content: &str) {
+        fs::write(path, content).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn test_program() {
+        assert_eq!(Pinning::program("west completion zsh"), Some("west"));
+        assert_eq!(Pinning::program("  "), None);
+    }
+
+    fn hashing() -> Settings {
+        let mut settings = Settings::default();
+        settings.pinning.hash = true;
+        settings
+    }
+
+    #[test]
+    fn test_detects_replaced_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let tool = dir.path().join("tool.sh");
+        write_executable(&tool, "#!/bin/sh\necho one\n");
+
+        let commands = vec![Command::ProcessSubstitution {
+            command: "./tool.sh completion".to_string(),
+        }];
+        let pins = Pinning::pin(&commands, dir.path(), &hashing()).unwrap();
+        assert_eq!(pins.len(), 1);
+        assert!(Pinning::differences(&pins, &commands, dir.path(), &hashing()).is_empty());
+
+        write_executable(&tool, "#!/bin/sh\necho two\n");
+        assert_eq!(Pinning::differences(&pins, &commands, dir.path(), &hashing()).len(), 1);
+
+        fs::remove_file(&tool).unwrap();
+        assert!(Pinning::differences(&pins, &commands, dir.path(), &hashing())[0].contains("no longer resolves"));
+    }
+
+    #[test]
+    fn test_resolves_on_the_path_of_earlier_directives() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(".venv/bin")).unwrap();
+        fs::write(dir.path().join(".venv/bin/activate"), "").unwrap();
+        let commands = Parser::parse("python_venv .venv\nsource <(durrrrrenv-test-tool completion)\n").unwrap();
+
+        // Not installed yet: pinned as missing, and reported once it shows up
+        let pins = Pinning::pin(&commands, dir.path(), &Settings::default()).unwrap();
+        assert_eq!(pins[0].path, None);
+        assert!(Pinning::differences(&pins, &commands, dir.path(), &Settings::default()).is_empty());
+
+        let tool = dir.path().join(".venv/bin/durrrrrenv-test-tool");
+        write_executable(&tool, "#!/bin/sh\n");
+        let differences = Pinning::differences(&pins, &commands, dir.path(), &Settings::default());
+        assert!(differences[0].contains("not found when approved"), "{:?}", differences);
+
+        let pins = Pinning::pin(&commands, dir.path(), &Settings::default()).unwrap();
+        assert_eq!(pins[0].path, Some(tool));
+
+        // A program added to the file later has no pin at all
+        let more = Parser::parse("python_venv .venv\nsource <(durrrrrenv-test-tool completion)\nsource <(./other)\n").unwrap();
+        let differences = Pinning::differences(&pins, &more, dir.path(), &Settings::default());
+        assert!(differences[0].contains("was not pinned"), "{:?}", differences);
+    }
+}
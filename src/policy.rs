@@ -0,0 +1,277 @@
+use crate::executor::Executor;
+use crate::nix::Nix;
+use crate::parser::Command;
+use crate::paths;
+use crate::python::Python;
+use crate::rust::Rust;
+use crate::settings::SigningSettings;
+use crate::signing;
+use crate::tool_versions::ToolVersions;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// System-wide policy managed by administrators. Not overridable by user config.
+pub const POLICY_PATH: &str = "/etc/durrrrrenv/policy.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    pub directives: DirectivePolicy,
+    pub paths: PathPolicy,
+    pub signatures: SignaturePolicy,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirectivePolicy {
+    /// Directive names that may not be used, e.g. "process_substitution"
+    pub disabled: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathPolicy {
+    /// Only allow files and directories that directives read inside the project directory
+    pub within_project: bool,
+    /// Extra locations allowed when `within_project` is set
+    pub allowed: Vec<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SignaturePolicy {
+    /// Only load files signed by a trusted key
+    pub required: bool,
+    /// Keys trusted by policy. When set, only these satisfy `required`.
+    pub trusted_keys: std::collections::BTreeMap<String, String>,
+}
+
+impl Policy {
+    /// Load the system policy. A missing file means no restrictions; a broken
+    /// one is an error so that a typo can't silently lift restrictions.
+    pub fn load() -> Result<Self> {
+        let path = Path::new(POLICY_PATH);
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        Self::from_toml(&contents).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Parse policy from TOML text
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    /// Keys that count as trusted signers under this policy
+    pub fn keyring(&self, user: &SigningSettings) -> SigningSettings {
+        if self.signatures.trusted_keys.is_empty() {
+            user.clone()
+        } else {
+            SigningSettings {
+                trusted_keys: self.signatures.trusted_keys.clone(),
+            }
+        }
+    }
+
+    /// Describe every way the env file breaks the policy
+    pub fn violations(
+        &self,
+        env_file: &Path,
+        content: &str,
+        commands: &[Command],
+        source_dir: &Path,
+        user_signing: &SigningSettings,
+    ) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        for cmd in commands {
+            let name = cmd.directive_name();
+            if self.directives.disabled.iter().any(|d| d == name) {
+                violations.push(format!("directive '{}' is disabled", name));
+            }
+        }
+
+        if self.paths.within_project {
+            let project = paths::canonical(source_dir);
+
+            for cmd in commands {
+                for (target, resolved) in Self::path_targets(cmd, source_dir) {
+                    match resolved {
+                        Ok(resolved) if self.path_allowed(&resolved, &project) => {}
+                        _ => violations.push(format!(
+                            "{} target '{}' is outside the project",
+                            cmd.directive_name(),
+                            target
+                        )),
+                    }
+                }
+            }
+        }
+
+        if self.signatures.required {
+            let keyring = self.keyring(user_signing);
+            match signing::verify_env_file(env_file, content, &keyring) {
+                Ok(Some(_)) => {}
+                Ok(None) => violations.push("a trusted signature is required".to_string()),
+                Err(e) => violations.push(format!("a trusted signature is required ({})", e)),
+            }
+        }
+
+        violations
+    }
+
+    /// Files and directories a directive reads, as written and resolved
+    fn path_targets(cmd: &Command, source_dir: &Path) -> Vec<(String, Result<PathBuf>)> {
+        match cmd {
+            Command::Source { path } => vec![(path.clone(), Executor::resolve_path(path, source_dir))],
+            // A venv `auto` can't find won't be loaded anyway
+            Command::PythonVenv { path, .. } => match Python::venv(path, source_dir) {
+                Ok(venv) => vec![(path.clone(), Ok(venv.path))],
+                Err(_) if Python::is_auto(path, source_dir) => Vec::new(),
+                Err(e) => vec![(path.clone(), Err(e))],
+            },
+            // Environments named rather than given by path live in conda's own directories
+            Command::CondaEnv { env } if env.contains('/') => {
+                vec![(env.clone(), Executor::resolve_path(env, source_dir))]
+            }
+            Command::UseNix { file } => {
+                let file = Nix::shell_file(file.as_deref(), source_dir);
+                let resolved = Executor::resolve_path(&file, source_dir);
+                vec![(file, resolved)]
+            }
+            // Only local flakes are paths; registry and remote references aren't
+            Command::UseFlake { reference } => {
+                let reference = reference.as_deref().unwrap_or(".");
+                match Nix::flake_dir(reference, source_dir) {
+                    Some(dir) => vec![(reference.to_string(), Ok(dir))],
+                    None => Vec::new(),
+                }
+            }
+            // Named toolchains live in rustup's own directory
+            Command::UseRust { toolchain } => Rust::local_path(toolchain.as_deref(), source_dir)
+                .map(|(path, dir)| vec![(path, Ok(dir))])
+                .unwrap_or_default(),
+            Command::UseToolVersions { file } => {
+                let resolved = ToolVersions::file(file.as_deref(), source_dir);
+                let mut targets = vec![(
+                    file.clone().unwrap_or_else(|| ".tool-versions".to_string()),
+                    Ok(resolved.clone()),
+                )];
+                targets.extend(
+                    ToolVersions::local_paths(&resolved)
+                        .into_iter()
+                        .map(|(version, path)| (version, Ok(path))),
+                );
+                targets
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn path_allowed(&self, path: &Path, project: &Path) -> bool {
+        let path = paths::normalize(&paths::canonical(path));
+
+        path.starts_with(project)
+            || self
+                .paths
+                .allowed
+                .iter()
+                .any(|allowed| path.starts_with(paths::canonical(allowed)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_disabled_directive() {
+        let policy = Policy::from_toml("[directives]\ndisabled = [\"process_substitution\"]\n").unwrap();
+        let commands = Parser::parse("python_venv\nsource <(west completion zsh)\n").unwrap();
+
+        let violations = policy.violations(
+            Path::new("/nonexistent/.local_environment"),
+            "",
+            &commands,
+            Path::new("/nonexistent"),
+            &SigningSettings::default(),
+        );
+        assert_eq!(violations, vec!["directive 'process_substitution' is disabled"]);
+    }
+
+    #[test]
+    fn test_source_within_project() {
+        let policy = Policy::from_toml("[paths]\nwithin_project = true\nallowed = [\"/opt/shared\"]\n").unwrap();
+        let commands =
+            Parser::parse("source scripts/env.sh\nsource ../other/env.sh\nsource /opt/shared/env.sh\n").unwrap();
+
+        let violations = policy.violations(
+            Path::new("/srv/app/.local_environment"),
+            "",
+            &commands,
+            Path::new("/srv/app"),
+            &SigningSettings::default(),
+        );
+        assert_eq!(violations, vec!["source target '../other/env.sh' is outside the project"]);
+    }
+
+    #[test]
+    fn test_directive_paths_within_project() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("app");
+        fs::create_dir(&project).unwrap();
+        fs::write(project.join(".tool-versions"), "nodejs 20.11.0\npython path:../python\nruby path:vendor/ruby\n").unwrap();
+        fs::write(project.join("rust-toolchain.toml"), "[toolchain]\npath = \"/opt/rust\"\n").unwrap();
+
+        let policy = Policy::from_toml("[paths]\nwithin_project = true\n").unwrap();
+        let commands = Parser::parse(
+            "conda_env /opt/envs/ml\nconda_env ml\nuse_nix ../shell.nix\nuse_flake ./nix#dev\nuse_flake github:org/repo\nuse_tool_versions\nuse_tool_versions ../.tool-versions\nuse_rust\nuse_rust stable\n",
+        )
+        .unwrap();
+
+        let violations = policy.violations(
+            &project.join(".local_environment"),
+            "",
+            &commands,
+            &project,
+            &SigningSettings::default(),
+        );
+        assert_eq!(
+            violations,
+            vec![
+                "conda_env target '/opt/envs/ml' is outside the project",
+                "use_nix target '../shell.nix' is outside the project",
+                "use_tool_versions target 'path:../python' is outside the project",
+                "use_tool_versions target '../.tool-versions' is outside the project",
+                "use_rust target '/opt/rust' is outside the project",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_signature_required() {
+        let policy = Policy::from_toml("[signatures]\nrequired = true\n").unwrap();
+
+        let violations = policy.violations(
+            Path::new("/nonexistent/.local_environment"),
+            "",
+            &[],
+            Path::new("/nonexistent"),
+            &SigningSettings::default(),
+        );
+        assert_eq!(violations, vec!["a trusted signature is required"]);
+    }
+
+    #[test]
+    fn test_rejects_unknown_keys() {
+        assert!(Policy::from_toml("[directives]\ndisable = [\"source\"]\n").is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::process::{Command, Stdio};
+
+/// Terminal the user is sitting at, independent of stdin/stdout redirection
+pub struct Tty {
+    file: File,
+}
+
+impl Tty {
+    /// Open the controlling terminal, or return None if there isn't one
+    pub fn open() -> Option<Self> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .ok()
+            .map(|file| Self { file })
+    }
+
+    /// Write text to the terminal
+    pub fn print(&mut self, text: &str) -> Result<()> {
+        self.file.write_all(text.as_bytes())?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    /// Show text, through $PAGER (or less) when it doesn't fit on the screen
+    pub fn page(&mut self, text: &str) -> Result<()> {
+        let fits = self
+            .rows()
+            .is_none_or(|rows| text.lines().count() < rows.saturating_sub(2));
+        if fits || self.run_pager(text).is_err() {
+            self.print(text)?;
+        }
+        Ok(())
+    }
+
+    fn run_pager(&mut self, text: &str) -> Result<()> {
+        let pager = std::env::var("PAGER")
+            .ok()
+            .filter(|p| !p.trim().is_empty())
+            .unwrap_or_else(|| "less".to_string());
+
+        // Run through the shell so PAGER may carry arguments, e.g. "less -R"
+        let mut child = Command::new("/bin/sh")
+            .arg("-c")
+            .arg(&pager)
+            .stdin(Stdio::piped())
+            .stdout(self.file.try_clone()?)
+            .stderr(self.file.try_clone()?)
+            .spawn()
+            .with_context(|| format!("Failed to start pager `{}`", pager))?;
+
+        // The pager may quit before reading everything; that's fine
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(text.as_bytes());
+        }
+
+        let status = child.wait()?;
+        if !status.success() {
+            anyhow::bail!("Pager `{}` failed ({})", pager, status);
+        }
+        Ok(())
+    }
+
+    /// Height of the terminal, if known
+    fn rows(&self) -> Option<usize> {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), libc::TIOCGWINSZ, &mut size) } != 0 || size.ws_row == 0 {
+            return None;
+        }
+        Some(size.ws_row as usize)
+    }
+
+    /// Show a prompt and read a single keystroke without waiting for Enter
+    pub fn read_key(&mut self, prompt: &str) -> Result<char> {
+        self.print(prompt)?;
+
+        let fd = self.file.as_raw_fd();
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+            // Not a real terminal; fall back to reading a whole line
+            let line = self.read_line("")?;
+            return Ok(line.chars().next().unwrap_or('\n'));
+        }
+
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) };
+
+        let mut byte = [0u8; 1];
+        let result = self.file.read_exact(&mut byte);
+
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+        result.context("Failed to read from terminal")?;
+
+        let key = byte[0] as char;
+        self.print(&format!("{}\n", if key.is_ascii_graphic() { key } else { ' ' }))?;
+
+        Ok(key)
+    }
+
+    /// Show a prompt and read a line of input
+    pub fn read_line(&mut self, prompt: &str) -> Result<String> {
+        self.print(prompt)?;
+
+        let mut line = Vec::new();
+        let mut byte = [0u8; 1];
+        while self.file.read(&mut byte)? == 1 && byte[0] != b'\n' {
+            line.push(byte[0]);
+        }
+
+        Ok(String::from_utf8_lossy(&line).trim().to_string())
+    }
+
+    /// Ask a yes/no question answered with a single keystroke
+    pub fn confirm(&mut self, prompt: &str) -> Result<bool> {
+        let key = self.read_key(prompt)?;
+        Ok(key.eq_ignore_ascii_case(&'y'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+
+    /// A Tty whose input is `input`. A socket is not a terminal, so keys are
+    /// read through the line fallback
+    fn tty_with_input(input: &str) -> (Tty, UnixStream) {
+        let (ours, mut theirs) = UnixStream::pair().unwrap();
+        theirs.write_all(input.as_bytes()).unwrap();
+        let file = File::from(std::os::fd::OwnedFd::from(ours));
+        (Tty { file }, theirs)
+    }
+
+    #[test]
+    fn test_confirm_accepts_only_y() {
+        let cases = [
+            ("y\n", true),
+            ("Y\n", true),
+            ("yes\n", true),
+            ("n\n", false),
+            ("\n", false),
+            ("x\n", false),
+        ];
+        for (input, expected) in cases {
+            let (mut tty, _other) = tty_with_input(input);
+            assert_eq!(tty.confirm("? ").unwrap(), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_read_key_takes_the_first_character() {
+        let (mut tty, _other) = tty_with_input("allow\n");
+        assert_eq!(tty.read_key("? ").unwrap(), 'a');
+
+        // Enter alone picks no choice
+        let (mut tty, _other) = tty_with_input("\n");
+        assert_eq!(tty.read_key("? ").unwrap(), '\n');
+    }
+
+    #[test]
+    fn test_read_line_trims_input() {
+        let (mut tty, mut other) = tty_with_input("  y \n");
+        assert_eq!(tty.read_line("Continue? ").unwrap(), "y");
+
+        // The prompt goes to the terminal, not to stdout
+        drop(tty);
+        let mut prompt = String::new();
+        other.read_to_string(&mut prompt).unwrap();
+        assert_eq!(prompt, "Continue? ");
+    }
+}
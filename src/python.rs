@@ -0,0 +1,534 @@
+use crate::executor::Executor;
+use crate::pinning::Pinning;
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::URL_SAFE;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command as ProcessCommand, Stdio};
+
+/// `python_venv auto`: look for the venv of whichever tool manages the project
+pub const AUTO: &str = "auto";
+
+/// A virtualenv picked for `python_venv`
+#[derive(Debug)]
+pub struct Venv {
+    pub path: PathBuf,
+    /// What chose it: "python_venv" for an explicit path, otherwise the
+    /// tool whose venv was found ("uv", "poetry", "pipenv", "hatch", ".venv")
+    pub tool: &'static str,
+}
+
+/// A Python interpreter to create a venv with
+#[derive(Debug, Clone)]
+pub struct Interpreter {
+    pub path: PathBuf,
+    pub version: String,
+    /// Where it was found: "pyenv" or "PATH"
+    pub origin: &'static str,
+}
+
+pub struct Python;
+
+impl Python {
+    /// The venv a `python_venv <path>` line refers to. Explicit paths are
+    /// returned as they are, existing or not.
+    pub fn venv(path: &str, working_dir: &Path) -> Result<Venv> {
+        if Self::is_auto(path, working_dir) {
+            return Self::discover(working_dir);
+        }
+
+        Ok(Venv {
+            path: Executor::resolve_path(path, working_dir)?,
+            tool: "python_venv",
+        })
+    }
+
+    /// Where `python_venv --create` should create the venv, if it doesn't
+    /// exist yet. `auto` creates .venv, which every tool above picks up
+    /// except Hatch.
+    pub fn missing_venv(path: &str, working_dir: &Path) -> Result<Option<PathBuf>> {
+        match Self::venv(path, working_dir) {
+            Ok(venv) if venv.path.join("bin/activate").exists() => Ok(None),
+            Ok(venv) => Ok(Some(venv.path)),
+            Err(_) if Self::is_auto(path, working_dir) => Ok(Some(working_dir.join(".venv"))),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether `path` asks for discovery. A venv that is actually called
+    /// `auto` wins, so projects that had one before keep loading it.
+    pub fn is_auto(path: &str, working_dir: &Path) -> bool {
+        path == AUTO && !working_dir.join(AUTO).join("bin/activate").exists()
+    }
+
+    /// Find an interpreter for the requested version, or the one in
+    /// .python-version: pyenv's versions first, then pythonX.Y on PATH
+    pub fn interpreter(requested: Option<&str>, working_dir: &Path) -> Result<Interpreter> {
+        let spec = Self::interpreter_spec(requested, working_dir);
+        let pyenv_versions = Self::pyenv_versions();
+        if let Some(interpreter) = spec
+            .as_deref()
+            .and_then(|spec| Self::select_pyenv(&pyenv_versions, spec))
+        {
+            return Ok(interpreter);
+        }
+
+        for program in Self::path_programs(spec.as_deref()) {
+            let Some(path) = Pinning::resolve(&program, working_dir, env::var("PATH").ok().as_deref()) else {
+                continue;
+            };
+            let Some(version) = Self::query_version(&path) else {
+                continue;
+            };
+            if spec.as_deref().is_none_or(|spec| Self::version_matches(&version, spec)) {
+                return Ok(Interpreter {
+                    path,
+                    version,
+                    origin: "PATH",
+                });
+            }
+        }
+
+        anyhow::bail!(
+            "No Python {} found in pyenv ({}) or on PATH",
+            spec.as_deref().unwrap_or("3"),
+            pyenv_versions.display()
+        )
+    }
+
+    /// What `create_venv` will install into a new venv from
+    pub fn install_source(working_dir: &Path) -> Option<&'static str> {
+        ["requirements.txt", "pyproject.toml"]
+            .into_iter()
+            .find(|file| working_dir.join(file).is_file())
+    }
+
+    /// Create a venv and install the project's requirements into it. The
+    /// tools' output goes to stderr, as stdout is evaluated by the hook.
+    pub fn create_venv(venv: &Path, interpreter: &Interpreter, working_dir: &Path) -> Result<()> {
+        Self::run(
+            ProcessCommand::new(&interpreter.path).arg("-m").arg("venv").arg(venv),
+            working_dir,
+        )
+        .with_context(|| format!("Failed to create venv at {}", venv.display()))?;
+
+        Self::pip_install(venv, working_dir)
+    }
+
+    /// Bring an existing venv up to date with the project's dependency files,
+    /// using the tool that manages it
+    pub fn sync(venv: &Venv, working_dir: &Path) -> Result<()> {
+        let tool_command = match venv.tool {
+            "uv" => Some(["uv", "sync"]),
+            "poetry" => Some(["poetry", "install"]),
+            "pipenv" if working_dir.join("Pipfile.lock").exists() => Some(["pipenv", "sync"]),
+            "pipenv" => Some(["pipenv", "install"]),
+            _ => None,
+        };
+
+        match tool_command {
+            Some([program, subcommand]) => Self::run(ProcessCommand::new(program).arg(subcommand), working_dir)
+                .with_context(|| format!("Failed to sync {}", venv.path.display())),
+            None => Self::pip_install(&venv.path, working_dir),
+        }
+    }
+
+    /// Install from requirements.txt or pyproject.toml with the venv's pip
+    fn pip_install(venv: &Path, working_dir: &Path) -> Result<()> {
+        let pip = venv.join("bin/pip");
+        match Self::install_source(working_dir) {
+            Some("requirements.txt") => Self::run(
+                ProcessCommand::new(&pip).args(["install", "-r", "requirements.txt"]),
+                working_dir,
+            ),
+            Some(_) => Self::run(ProcessCommand::new(&pip).args(["install", "-e", "."]), working_dir),
+            None => Ok(()),
+        }
+        .context("Failed to install the project's requirements")
+    }
+
+    fn run(command: &mut ProcessCommand, working_dir: &Path) -> Result<()> {
+        let status = command
+            .current_dir(working_dir)
+            .stdin(Stdio::null())
+            .stdout(io::stderr())
+            .status()
+            .with_context(|| format!("Failed to run {}", command.get_program().to_string_lossy()))?;
+        if !status.success() {
+            anyhow::bail!("{} exited with {}", command.get_program().to_string_lossy(), status);
+        }
+        Ok(())
+    }
+
+    /// First version named in .python-version, unless it defers to the system Python
+    /// Which interpreter `interpreter` would pick, worked out without running
+    /// any Python: the matching pyenv version, or what it would try on PATH
+    pub fn describe_interpreter(requested: Option<&str>, working_dir: &Path) -> String {
+        let spec = Self::interpreter_spec(requested, working_dir);
+        if let Some(interpreter) = spec
+            .as_deref()
+            .and_then(|spec| Self::select_pyenv(&Self::pyenv_versions(), spec))
+        {
+            return format!("Python {} (pyenv, {})", interpreter.version, interpreter.path.display());
+        }
+
+        let programs = Self::path_programs(spec.as_deref()).join(" or ");
+        match spec {
+            Some(spec) => format!("Python {} ({} on PATH)", spec, programs),
+            None => format!("{} on PATH", programs),
+        }
+    }
+
+    /// The requested version, or the one in .python-version
+    fn interpreter_spec(requested: Option<&str>, working_dir: &Path) -> Option<String> {
+        match requested {
+            Some(version) => Some(version.to_string()),
+            None => Self::python_version_file(working_dir),
+        }
+    }
+
+    fn pyenv_versions() -> PathBuf {
+        env::var_os("PYENV_ROOT")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| dirs::home_dir().unwrap_or_default().join(".pyenv"))
+            .join("versions")
+    }
+
+    /// Programs to try on PATH for a version spec, most specific first
+    fn path_programs(spec: Option<&str>) -> Vec<String> {
+        match spec {
+            Some(spec) => {
+                let major_minor: Vec<&str> = spec.split('.').take(2).collect();
+                vec![format!("python{}", major_minor.join(".")), "python3".to_string()]
+            }
+            None => vec!["python3".to_string()],
+        }
+    }
+
+    fn python_version_file(working_dir: &Path) -> Option<String> {
+        fs::read_to_string(working_dir.join(".python-version"))
+            .ok()?
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .and_then(|line| line.split_whitespace().next())
+            .filter(|version| *version != "system")
+            .map(str::to_string)
+    }
+
+    /// The newest pyenv version the spec matches: by name, or by version prefix
+    fn select_pyenv(versions_dir: &Path, spec: &str) -> Option<Interpreter> {
+        fs::read_dir(versions_dir)
+            .ok()?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name == spec || Self::version_matches(name, spec))
+            .filter(|name| versions_dir.join(name).join("bin/python").exists())
+            .max_by_key(|name| Self::components(name))
+            .map(|name| Interpreter {
+                path: versions_dir.join(&name).join("bin/python"),
+                version: name,
+                origin: "pyenv",
+            })
+    }
+
+    /// `3.11` matches 3.11.x; only plain CPython versions take part
+    fn version_matches(version: &str, spec: &str) -> bool {
+        let wanted = Self::components(spec);
+        let components = Self::components(version);
+        !wanted.is_empty() && version.starts_with(|c: char| c.is_ascii_digit()) && components.starts_with(&wanted)
+    }
+
+    fn components(version: &str) -> Vec<u64> {
+        version.split('.').map_while(|part| part.parse().ok()).collect()
+    }
+
+    /// Version reported by `python --version`, e.g. "3.11.4"
+    fn query_version(python: &Path) -> Option<String> {
+        let output = ProcessCommand::new(python).arg("--version").output().ok()?;
+        let text = String::from_utf8_lossy(&output.stdout);
+        text.trim().strip_prefix("Python ").map(str::to_string)
+    }
+
+    /// Try the venv of each tool the project uses, then plain .venv
+    fn discover(project: &Path) -> Result<Venv> {
+        let pyproject = fs::read_to_string(project.join("pyproject.toml"))
+            .ok()
+            .and_then(|contents| toml::from_str::<toml::Table>(&contents).ok())
+            .unwrap_or_default();
+        let has_tool = |name: &str| pyproject.get("tool").and_then(|tool| tool.get(name)).is_some();
+
+        let mut candidates: Vec<(&'static str, Result<PathBuf>)> = Vec::new();
+        if project.join("uv.lock").exists() || has_tool("uv") {
+            candidates.push(("uv", Ok(Self::uv_venv(project))));
+        }
+        if project.join("poetry.lock").exists() || has_tool("poetry") {
+            candidates.push(("poetry", Self::poetry_venv(project, &pyproject)));
+        }
+        if project.join("Pipfile").exists() {
+            candidates.push(("pipenv", Self::pipenv_venv(project)));
+        }
+        if project.join("hatch.toml").exists() || has_tool("hatch") {
+            candidates.push(("hatch", Self::hatch_venv(project, &pyproject)));
+        }
+        candidates.push((".venv", Ok(project.join(".venv"))));
+
+        let mut tried = Vec::new();
+        for (tool, path) in candidates {
+            match path {
+                Ok(path) if path.join("bin/activate").exists() => return Ok(Venv { path, tool }),
+                Ok(path) => tried.push(format!("{}: no venv at {}", tool, path.display())),
+                Err(e) => tried.push(format!("{}: {:#}", tool, e)),
+            }
+        }
+
+        anyhow::bail!("python_venv auto found no virtualenv ({})", tried.join("; "))
+    }
+
+    /// uv keeps the project venv in .venv unless UV_PROJECT_ENVIRONMENT says otherwise
+    fn uv_venv(project: &Path) -> PathBuf {
+        match env::var_os("UV_PROJECT_ENVIRONMENT") {
+            Some(path) => project.join(path),
+            None => project.join(".venv"),
+        }
+    }
+
+    /// Poetry's venv: in-project .venv when configured or present, otherwise
+    /// `<name>-<hash>-py<X.Y>` under its virtualenvs directory
+    fn poetry_venv(project: &Path, pyproject: &toml::Table) -> Result<PathBuf> {
+        let config_dir = dirs::config_dir().unwrap_or_default().join("pypoetry");
+        let local = Self::read_toml(&project.join("poetry.toml"));
+        let global = Self::read_toml(&config_dir.join("config.toml"));
+        let setting = |env_var: &str, key: &str| {
+            env::var(env_var).ok().map(toml::Value::String).or_else(|| {
+                [&local, &global]
+                    .into_iter()
+                    .find_map(|config| config.get("virtualenvs")?.get(key).cloned())
+            })
+        };
+
+        let in_project = match setting("POETRY_VIRTUALENVS_IN_PROJECT", "in-project") {
+            Some(toml::Value::Boolean(value)) => Some(value),
+            Some(toml::Value::String(value)) => Some(matches!(value.as_str(), "true" | "1")),
+            _ => None,
+        };
+        let in_project_venv = project.join(".venv");
+        if in_project == Some(true) || (in_project.is_none() && in_project_venv.is_dir()) {
+            return Ok(in_project_venv);
+        }
+
+        let virtualenvs = match setting("POETRY_VIRTUALENVS_PATH", "path") {
+            Some(toml::Value::String(path)) => Executor::resolve_path(&path, project)?,
+            _ => {
+                let cache_dir = match env::var("POETRY_CACHE_DIR").ok().or_else(|| {
+                    [&local, &global]
+                        .into_iter()
+                        .find_map(|config| config.get("cache-dir")?.as_str().map(str::to_string))
+                }) {
+                    Some(dir) => Executor::resolve_path(&dir, project)?,
+                    None => dirs::cache_dir().unwrap_or_default().join("pypoetry"),
+                };
+                cache_dir.join("virtualenvs")
+            }
+        };
+
+        let name = pyproject
+            .get("tool")
+            .and_then(|tool| tool.get("poetry"))
+            .and_then(|poetry| poetry.get("name"))
+            .or_else(|| pyproject.get("project")?.get("name"))
+            .and_then(|name| name.as_str())
+            .map(str::to_string)
+            .or_else(|| Some(project.file_name()?.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+        let base = Self::poetry_env_name(&name, &fs::canonicalize(project)?);
+
+        // envs.toml records the Python version `poetry env use` last picked
+        let minor = Self::read_toml(&virtualenvs.join("envs.toml"))
+            .get(&base)
+            .and_then(|env| env.get("minor")?.as_str().map(str::to_string));
+        if let Some(minor) = minor {
+            return Ok(virtualenvs.join(format!("{}-py{}", base, minor)));
+        }
+
+        // Otherwise the newest Python it has a venv for
+        let prefix = format!("{}-py", base);
+        fs::read_dir(&virtualenvs)
+            .ok()
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter_map(|dir| {
+                let version: Vec<u64> = dir
+                    .strip_prefix(&prefix)?
+                    .split('.')
+                    .map(|part| part.parse().ok())
+                    .collect::<Option<_>>()?;
+                Some((version, dir))
+            })
+            .max()
+            .map(|(_, dir)| virtualenvs.join(dir))
+            .ok_or_else(|| anyhow::anyhow!("no venv named {}-py* in {}", base, virtualenvs.display()))
+    }
+
+    /// Poetry's name for a project's venvs, without the Python suffix:
+    /// sanitized name, then a hash of the project's real path
+    fn poetry_env_name(name: &str, project: &Path) -> String {
+        let sanitized = Self::sanitize(&name.to_lowercase(), " $`!*@\"\\\r\n\t");
+        let digest = Sha256::digest(project.to_string_lossy().as_bytes());
+        format!("{}-{}", sanitized, &URL_SAFE.encode(digest)[..8])
+    }
+
+    /// Pipenv's venv: .venv when in-project, otherwise `<dir name>-<hash>`
+    /// under WORKON_HOME
+    fn pipenv_venv(project: &Path) -> Result<PathBuf> {
+        let in_project = project.join(".venv");
+        if env::var("PIPENV_VENV_IN_PROJECT").is_ok_and(|value| matches!(value.as_str(), "1" | "true"))
+            || in_project.is_dir()
+        {
+            return Ok(in_project);
+        }
+
+        let workon_home = match env::var("WORKON_HOME") {
+            Ok(dir) => Executor::resolve_path(&dir, project)?,
+            Err(_) => dirs::home_dir().unwrap_or_default().join(".local/share/virtualenvs"),
+        };
+
+        let name = match env::var("PIPENV_CUSTOM_VENV_NAME") {
+            Ok(name) => name,
+            Err(_) => Self::pipenv_env_name(&fs::canonicalize(project.join("Pipfile"))?),
+        };
+        Ok(workon_home.join(name))
+    }
+
+    /// Pipenv's venv name: sanitized project directory name, then a hash of the Pipfile's path
+    fn pipenv_env_name(pipfile: &Path) -> String {
+        let dir_name = pipfile
+            .parent()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let sanitized = Self::sanitize(&dir_name, " &$`!*@\"()[]\\\r\n\t");
+        let digest = Sha256::digest(pipfile.to_string_lossy().as_bytes());
+        format!("{}-{}", sanitized, URL_SAFE.encode(&digest[..6]))
+    }
+
+    /// Hatch's default environment: the configured path, or the one venv it
+    /// created for the project under its data directory
+    fn hatch_venv(project: &Path, pyproject: &toml::Table) -> Result<PathBuf> {
+        let hatch_toml = Self::read_toml(&project.join("hatch.toml"));
+        let configured = hatch_toml
+            .get("envs")
+            .or_else(|| pyproject.get("tool")?.get("hatch")?.get("envs"))
+            .and_then(|envs| envs.get("default")?.get("path")?.as_str());
+        if let Some(path) = configured {
+            return Executor::resolve_path(path, project);
+        }
+
+        let data_dir = match env::var("HATCH_DATA_DIR") {
+            Ok(dir) => PathBuf::from(dir),
+            Err(_) => dirs::data_dir().unwrap_or_default().join("hatch"),
+        };
+        let name = pyproject
+            .get("project")
+            .and_then(|project| project.get("name")?.as_str())
+            .map(str::to_string)
+            .or_else(|| Some(project.file_name()?.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+
+        // <data>/env/virtual/<name>/<project id>/<name>
+        let project_dir = data_dir.join("env/virtual").join(&name);
+        let venvs: Vec<PathBuf> = fs::read_dir(&project_dir)
+            .ok()
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| Some(entry.ok()?.path().join(&name)))
+            .filter(|venv| venv.is_dir())
+            .collect();
+
+        match &venvs[..] {
+            [venv] => Ok(venv.clone()),
+            [] => anyhow::bail!("no venv in {}", project_dir.display()),
+            _ => anyhow::bail!(
+                "several venvs in {}; set tool.hatch.envs.default.path to pick one",
+                project_dir.display()
+            ),
+        }
+    }
+
+    /// Replace characters in `dangerous` with '_' and cut the result to 42
+    /// characters, as Poetry and Pipenv do
+    fn sanitize(name: &str, dangerous: &str) -> String {
+        name.chars()
+            .map(|c| if dangerous.contains(c) { '_' } else { c })
+            .take(42)
+            .collect()
+    }
+
+    fn read_toml(path: &Path) -> toml::Table {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_names_match_tools() {
+        // Names Poetry and Pipenv generate for these paths
+        assert_eq!(
+            Python::poetry_env_name("My Project", Path::new("/home/user/my-project")),
+            "my_project-x-L3W1O5"
+        );
+        assert_eq!(
+            Python::pipenv_env_name(Path::new("/home/user/my-project/Pipfile")),
+            "my-project-rQLlkrcH"
+        );
+    }
+
+    #[test]
+    fn test_auto_prefers_managing_tool() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path();
+        fs::create_dir_all(project.join(".venv/bin")).unwrap();
+        fs::write(project.join(".venv/bin/activate"), "").unwrap();
+
+        let venv = Python::venv(AUTO, project).unwrap();
+        assert_eq!((venv.path, venv.tool), (project.join(".venv"), ".venv"));
+
+        fs::write(project.join("uv.lock"), "").unwrap();
+        assert_eq!(Python::venv(AUTO, project).unwrap().tool, "uv");
+
+        fs::remove_file(project.join(".venv/bin/activate")).unwrap();
+        let error = Python::venv(AUTO, project).unwrap_err().to_string();
+        assert!(error.contains("uv: no venv at"));
+
+        // A venv directory named `auto` is taken literally
+        fs::create_dir_all(project.join("auto/bin")).unwrap();
+        fs::write(project.join("auto/bin/activate"), "").unwrap();
+        let venv = Python::venv(AUTO, project).unwrap();
+        assert_eq!((venv.path, venv.tool), (project.join("auto"), "python_venv"));
+    }
+
+    #[test]
+    fn test_select_pyenv_version() {
+        let versions = tempfile::tempdir().unwrap();
+        for name in ["3.10.13", "3.11.4", "3.11.9", "3.12-dev", "pypy3.10-7.3.12"] {
+            fs::create_dir_all(versions.path().join(name).join("bin")).unwrap();
+            fs::write(versions.path().join(name).join("bin/python"), "").unwrap();
+        }
+
+        let pick = |spec| Python::select_pyenv(versions.path(), spec).map(|python| python.version);
+        assert_eq!(pick("3.11").as_deref(), Some("3.11.9"));
+        assert_eq!(pick("3.11.4").as_deref(), Some("3.11.4"));
+        assert_eq!(pick("pypy3.10-7.3.12").as_deref(), Some("pypy3.10-7.3.12"));
+        assert_eq!(pick("3.9"), None);
+    }
+}
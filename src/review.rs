@@ -0,0 +1,326 @@
+use crate::conda::Conda;
+use crate::executor::Executor;
+use crate::jdk::Jdk;
+use crate::nix::{Nix, Shell};
+use crate::node::Node;
+use crate::parser::Command;
+use crate::pinning::Pinning;
+use crate::python::Python;
+use crate::ruby::Ruby;
+use crate::rust::Rust;
+use crate::tool_versions::ToolVersions;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Sourced files larger than this are summarized instead of shown in full
+const MAX_SHOWN_FILE_SIZE: u64 = 256 * 1024;
+
+/// Files sourced by sourced files are followed this many levels deep
+const MAX_SOURCE_DEPTH: usize = 5;
+
+pub struct Review;
+
+impl Review {
+    /// Everything a .local_environment file will pull in, for the approval prompt:
+    /// the file itself, the contents of every local file it sources, the venv
+    /// activate scripts and the binaries that process substitutions will run
+    pub fn summary(content: &str, commands: &[Command], working_dir: &Path) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(out, "Contents of .local_environment:");
+        let _ = writeln!(out, "---");
+        let _ = writeln!(out, "{}", content.trim_end());
+        let _ = writeln!(out, "---");
+
+        for cmd in commands {
+            match cmd {
+                Command::Source { path } => {
+                    Self::describe_source(&mut out, path, working_dir, None, &mut Vec::new())
+                }
+                Command::PythonVenv { path, create, python } => {
+                    let _ = writeln!(out, "\n{}", cmd);
+                    match Python::venv(path, working_dir) {
+                        Ok(venv) => {
+                            let activate = venv.path.join("bin").join("activate");
+                            let state = if activate.exists() { "" } else { " (missing)" };
+                            let _ = writeln!(out, "  activates {}{}", activate.display(), state);
+                            if Python::is_auto(path, working_dir) {
+                                let _ = writeln!(out, "  found as the {} venv", venv.tool);
+                            }
+                        }
+                        Err(e) if !*create => {
+                            let _ = writeln!(out, "  cannot resolve: {}", e);
+                        }
+                        Err(_) => {}
+                    }
+
+                    if let (true, Ok(Some(venv))) = (*create, Python::missing_venv(path, working_dir)) {
+                        // Reviewing must not run anything, so no `python --version` here
+                        let interpreter = Python::describe_interpreter(python.as_deref(), working_dir);
+                        let _ = writeln!(out, "  offers to create {} with {}", venv.display(), interpreter);
+                        if let Some(file) = Python::install_source(working_dir) {
+                            let _ = writeln!(out, "  and to install the project from {} into it", file);
+                        }
+                    }
+                }
+                Command::ProcessSubstitution { command } => {
+                    let _ = writeln!(out, "\n{}", cmd);
+                    let program = Pinning::program(command).unwrap_or_default();
+                    match Pinning::resolve(program, working_dir, env::var("PATH").ok().as_deref()) {
+                        Some(binary) => {
+                            let _ = writeln!(out, "  runs {}", binary.display());
+                        }
+                        None => {
+                            let _ = writeln!(out, "  runs `{}` (not found on PATH)", program);
+                        }
+                    }
+                }
+                Command::UseNode { version } => {
+                    let _ = writeln!(out, "\n{}", cmd);
+                    match Node::resolve(version.as_deref(), working_dir) {
+                        Ok(node) => {
+                            let _ = writeln!(
+                                out,
+                                "  puts Node {} ({}) on PATH: {}",
+                                node.version,
+                                node.manager,
+                                node.bin.display()
+                            );
+                        }
+                        Err(e) => {
+                            let _ = writeln!(out, "  {:#}", e);
+                        }
+                    }
+                }
+                Command::UseJdk { version } => {
+                    let _ = writeln!(out, "\n{}", cmd);
+                    match Jdk::resolve(version) {
+                        Ok(jdk) => {
+                            let _ = writeln!(out, "  sets JAVA_HOME={} (Java {})", jdk.home.display(), jdk.version);
+                        }
+                        Err(e) => {
+                            let _ = writeln!(out, "  {:#}", e);
+                        }
+                    }
+                }
+                Command::UseRuby { version } => {
+                    let _ = writeln!(out, "\n{}", cmd);
+                    match Ruby::resolve(version.as_deref(), working_dir) {
+                        Ok(ruby) => {
+                            let _ = writeln!(
+                                out,
+                                "  puts {} ({}) on PATH, gems in {}",
+                                ruby.ruby.name,
+                                ruby.ruby.manager,
+                                ruby.gem_home.display()
+                            );
+                        }
+                        Err(e) => {
+                            let _ = writeln!(out, "  {:#}", e);
+                        }
+                    }
+                }
+                Command::UseRust { toolchain } => {
+                    let _ = writeln!(out, "\n{}", cmd);
+                    match Rust::resolve(toolchain.as_deref(), working_dir) {
+                        Ok(toolchain) => {
+                            let _ = writeln!(
+                                out,
+                                "  sets RUSTUP_TOOLCHAIN={} (from {}) and puts {} on PATH",
+                                toolchain.name,
+                                toolchain.origin,
+                                toolchain.dir.join("bin").display()
+                            );
+                        }
+                        Err(e) => {
+                            let _ = writeln!(out, "  {:#}", e);
+                        }
+                    }
+                }
+                Command::UseNix { file } => {
+                    let _ = writeln!(out, "\n{}", cmd);
+                    let file = Nix::shell_file(file.as_deref(), working_dir);
+                    Self::describe_nix_shell(&mut out, Shell::NixShell(&file), "nix-shell", working_dir);
+                }
+                Command::UseFlake { reference } => {
+                    let _ = writeln!(out, "\n{}", cmd);
+                    let reference = reference.as_deref().unwrap_or(".");
+                    Self::describe_nix_shell(&mut out, Shell::Flake(reference), "nix print-dev-env", working_dir);
+                }
+                Command::UseToolVersions { file } => {
+                    let _ = writeln!(out, "\n{}", cmd);
+                    match ToolVersions::resolve(file.as_deref(), working_dir) {
+                        Ok(resolution) => {
+                            for tool in &resolution.tools {
+                                let _ = writeln!(
+                                    out,
+                                    "  puts {} {} ({}) on PATH: {}",
+                                    tool.name,
+                                    tool.version,
+                                    tool.manager,
+                                    tool.bin.display()
+                                );
+                            }
+                            for tool in &resolution.missing {
+                                let _ = writeln!(out, "  skips {} (not installed)", tool);
+                            }
+                        }
+                        Err(e) => {
+                            let _ = writeln!(out, "  {:#}", e);
+                        }
+                    }
+                }
+                Command::CondaEnv { env } => {
+                    let _ = writeln!(out, "\n{}", cmd);
+                    match Conda::resolve(env, working_dir) {
+                        Ok(env) => {
+                            let _ = writeln!(out, "  activates {} and puts its bin on PATH", env.prefix.display());
+                            for (name, value) in &env.env_vars {
+                                let _ = writeln!(out, "  sets {}={}", name, value);
+                            }
+                            for script in &env.activate_scripts {
+                                let _ = writeln!(out, "  sources {}", script.display());
+                            }
+                            for script in &env.deactivate_scripts {
+                                let _ = writeln!(out, "  sources {} on unload", script.display());
+                            }
+                        }
+                        Err(e) => {
+                            let _ = writeln!(out, "  {:#}", e);
+                        }
+                    }
+                }
+            }
+        }
+
+        out
+    }
+
+    fn describe_nix_shell(out: &mut String, shell: Shell, program: &str, working_dir: &Path) {
+        if Nix::is_cached(shell, working_dir) {
+            let _ = writeln!(out, "  loads the environment cached from an earlier `{}`", program);
+        } else {
+            let _ = writeln!(out, "  runs `{}` and caches the environment it sets up", program);
+        }
+
+        for file in Nix::local_files(shell, working_dir) {
+            let _ = writeln!(out, "  evaluated from {}", file.display());
+            Self::show_file(out, &file);
+        }
+    }
+
+    /// Show a sourced file, then the files it sources in turn. `chain` holds
+    /// the files already being shown, which stops loops and bounds the depth.
+    fn describe_source(
+        out: &mut String,
+        path: &str,
+        working_dir: &Path,
+        from: Option<&Path>,
+        chain: &mut Vec<PathBuf>,
+    ) {
+        let from = from
+            .map(|parent| format!(" (sourced by {})", parent.display()))
+            .unwrap_or_default();
+        let resolved = match Executor::resolve_path(path, working_dir) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                let _ = writeln!(out, "\nsource {}{}\n  cannot resolve: {}", path, from, e);
+                return;
+            }
+        };
+
+        let _ = writeln!(out, "\nsource {} -> {}{}", path, resolved.display(), from);
+        if chain.contains(&resolved) {
+            let _ = writeln!(out, "  (already shown above)");
+            return;
+        }
+
+        let Some(text) = Self::show_file(out, &resolved) else {
+            return;
+        };
+
+        chain.push(resolved.clone());
+        for nested in text.lines().filter_map(Self::sourced_path) {
+            if nested.contains(['$', '`', '(']) {
+                let _ = writeln!(
+                    out,
+                    "\nsource {} (sourced by {})\n  not expanded; review it separately",
+                    nested,
+                    resolved.display()
+                );
+            } else if chain.len() > MAX_SOURCE_DEPTH {
+                let _ = writeln!(
+                    out,
+                    "\nsource {} (sourced by {})\n  nested too deep to show; review it separately",
+                    nested,
+                    resolved.display()
+                );
+            } else {
+                Self::describe_source(out, nested, working_dir, Some(&resolved), chain);
+            }
+        }
+        chain.pop();
+    }
+
+    /// Show a file's contents between `---` lines, unless it is missing or too
+    /// large. Returns the contents if they were shown.
+    fn show_file(out: &mut String, path: &Path) -> Option<String> {
+        match fs::metadata(path) {
+            Err(_) => {
+                let _ = writeln!(out, "  (not found)");
+                None
+            }
+            Ok(metadata) if metadata.len() > MAX_SHOWN_FILE_SIZE => {
+                let _ = writeln!(out, "  ({} bytes, too large to show; review it separately)", metadata.len());
+                None
+            }
+            Ok(_) => match fs::read_to_string(path) {
+                Ok(text) => {
+                    let _ = writeln!(out, "---");
+                    let _ = writeln!(out, "{}", text.trim_end());
+                    let _ = writeln!(out, "---");
+                    Some(text)
+                }
+                Err(e) => {
+                    let _ = writeln!(out, "  (could not read: {})", e);
+                    None
+                }
+            },
+        }
+    }
+
+    /// The file a `source file` or `. file` line of a shell script reads
+    fn sourced_path(line: &str) -> Option<&str> {
+        let line = line.trim_start();
+        let rest = line.strip_prefix("source ").or_else(|| line.strip_prefix(". "))?;
+        let path = rest.split_whitespace().next()?;
+        Some(path.trim_matches(['"', '\'']))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_summary_expands_sourced_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("setup.sh"), "export SECRET_SAUCE=1\n. \"more.sh\"\nsource $HOME/x.sh\n").unwrap();
+        fs::write(dir.path().join("more.sh"), "export MORE_SAUCE=1\nsource setup.sh\n").unwrap();
+
+        let content = "source setup.sh\npython_venv\nsource <(definitely-not-a-real-binary completion)\n";
+        let commands = Parser::parse(content).unwrap();
+        let summary = Review::summary(content, &commands, dir.path());
+
+        assert!(summary.contains("export SECRET_SAUCE=1"));
+        // Nested files are shown too, once, and unexpandable paths are pointed out
+        assert!(summary.contains("export MORE_SAUCE=1"));
+        assert!(summary.contains("(already shown above)"));
+        assert!(summary.contains("source $HOME/x.sh (sourced by"));
+        assert!(summary.contains(".venv/bin/activate (missing)"));
+        assert!(summary.contains("`definitely-not-a-real-binary` (not found on PATH)"));
+    }
+}
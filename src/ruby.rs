@@ -0,0 +1,200 @@
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// An installed Ruby
+#[derive(Debug, Clone)]
+pub struct Installation {
+    /// Directory name, e.g. "3.2.2", "ruby-3.2.2" or "jruby-9.4.5.0"
+    pub name: String,
+    /// Installation prefix, containing bin/ruby
+    pub prefix: PathBuf,
+    /// Version manager that installed it
+    pub manager: &'static str,
+}
+
+/// Where a project's Ruby and gems live
+#[derive(Debug)]
+pub struct RubyEnv {
+    pub ruby: Installation,
+    /// Project-scoped gem directory, used as GEM_HOME
+    pub gem_home: PathBuf,
+    /// Ruby's own gem directory with the default gems, if present
+    pub gem_root: Option<PathBuf>,
+}
+
+pub struct Ruby;
+
+impl Ruby {
+    /// Find the Ruby for `use_ruby [version]`. Without a version the
+    /// project's .ruby-version decides.
+    pub fn resolve(version: Option<&str>, working_dir: &Path) -> Result<RubyEnv> {
+        let (spec, origin) = match version {
+            Some(version) => (version.to_string(), "use_ruby".to_string()),
+            None => {
+                let file = working_dir.join(".ruby-version");
+                let contents = fs::read_to_string(&file).with_context(|| {
+                    format!("use_ruby needs a version: none given and no .ruby-version in {}", working_dir.display())
+                })?;
+                let spec = contents
+                    .split_whitespace()
+                    .next()
+                    .context(".ruby-version is empty")?;
+                (spec.to_string(), ".ruby-version".to_string())
+            }
+        };
+
+        let ruby = Self::select(&spec, &Self::installations())
+            .with_context(|| format!("Cannot use Ruby '{}' (from {})", spec, origin))?;
+
+        let api_version = Self::api_version(&ruby.name);
+        let gem_root = api_version
+            .as_ref()
+            .map(|api| ruby.prefix.join("lib/ruby/gems").join(api))
+            .filter(|dir| dir.is_dir());
+        let gem_home = working_dir
+            .join(".gem")
+            .join(Self::engine(&ruby.name))
+            .join(api_version.unwrap_or_else(|| Self::version_part(&ruby.name).to_string()));
+
+        Ok(RubyEnv {
+            ruby,
+            gem_home,
+            gem_root,
+        })
+    }
+
+    /// Pick the newest installation matching the spec. `3.2` matches any
+    /// 3.2.x; `ruby-` is optional; other engines must be named (`jruby-9.4`).
+    fn select(spec: &str, installations: &[Installation]) -> Result<Installation> {
+        let wanted_engine = Self::engine(spec);
+        let wanted = Self::components(Self::version_part(spec));
+
+        if let Some(best) = installations
+            .iter()
+            .filter(|ruby| Self::engine(&ruby.name) == wanted_engine)
+            .filter(|ruby| Self::components(Self::version_part(&ruby.name)).starts_with(&wanted))
+            .max_by(|a, b| {
+                Self::components(Self::version_part(&a.name))
+                    .cmp(&Self::components(Self::version_part(&b.name)))
+            })
+        {
+            return Ok(best.clone());
+        }
+
+        if installations.is_empty() {
+            anyhow::bail!("no Rubies found under rbenv, chruby or rvm");
+        }
+
+        let mut installed: Vec<String> = installations
+            .iter()
+            .map(|ruby| format!("{} ({})", ruby.name, ruby.manager))
+            .collect();
+        installed.sort();
+        anyhow::bail!("no installed Ruby matches. Installed: {}", installed.join(", "))
+    }
+
+    /// Interpreter name: "ruby" unless the name says otherwise ("jruby-9.4")
+    fn engine(name: &str) -> &str {
+        match name.split_once('-') {
+            Some((engine, _)) if !engine.starts_with(|c: char| c.is_ascii_digit()) => engine,
+            _ => "ruby",
+        }
+    }
+
+    /// Version without the engine prefix
+    fn version_part(name: &str) -> &str {
+        match name.split_once('-') {
+            Some((engine, version)) if !engine.starts_with(|c: char| c.is_ascii_digit()) => version,
+            _ => name,
+        }
+    }
+
+    fn components(version: &str) -> Vec<u64> {
+        version
+            .split(['.', '-', 'p'])
+            .map_while(|part| part.parse().ok())
+            .collect()
+    }
+
+    /// RubyGems ABI directory for MRI versions: 3.2.2 -> 3.2.0
+    fn api_version(name: &str) -> Option<String> {
+        if Self::engine(name) != "ruby" {
+            return None;
+        }
+        match Self::components(Self::version_part(name))[..] {
+            [major, minor, ..] => Some(format!("{}.{}.0", major, minor)),
+            _ => None,
+        }
+    }
+
+    /// Every Ruby installed by rbenv, chruby or rvm
+    fn installations() -> Vec<Installation> {
+        let home = dirs::home_dir().unwrap_or_default();
+        let root = |var: &str, default: PathBuf| env::var_os(var).map(PathBuf::from).unwrap_or(default);
+
+        let mut installations = Vec::new();
+        installations.extend(Self::scan("rbenv", &root("RBENV_ROOT", home.join(".rbenv")).join("versions")));
+        installations.extend(Self::scan("chruby", Path::new("/opt/rubies")));
+        installations.extend(Self::scan("chruby", &home.join(".rubies")));
+        installations.extend(Self::scan("rvm", &root("rvm_path", home.join(".rvm")).join("rubies")));
+        installations
+    }
+
+    /// Rubies installed as `<dir>/<name>/bin/ruby`
+    fn scan(manager: &'static str, dir: &Path) -> Vec<Installation> {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+
+        entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let name = entry.file_name().into_string().ok()?;
+                let prefix = entry.path();
+
+                prefix.join("bin/ruby").exists().then_some(Installation {
+                    name,
+                    prefix,
+                    manager,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn installed(names: &[&str]) -> Vec<Installation> {
+        names
+            .iter()
+            .map(|name| Installation {
+                name: name.to_string(),
+                prefix: PathBuf::from("/rubies").join(name),
+                manager: "chruby",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_select_ruby() {
+        let installations = installed(&["ruby-3.1.4", "ruby-3.2.2", "3.2.10", "jruby-9.4.5.0"]);
+        let pick = |spec| Ruby::select(spec, &installations).unwrap().name;
+
+        assert_eq!(pick("3.2"), "3.2.10");
+        assert_eq!(pick("ruby-3.2.2"), "ruby-3.2.2");
+        assert_eq!(pick("3"), "3.2.10");
+        assert_eq!(pick("jruby-9.4"), "jruby-9.4.5.0");
+        assert!(Ruby::select("3.3", &installations).is_err());
+    }
+
+    #[test]
+    fn test_api_version() {
+        assert_eq!(Ruby::api_version("ruby-3.2.2").as_deref(), Some("3.2.0"));
+        assert_eq!(Ruby::api_version("2.7.8-p225").as_deref(), Some("2.7.0"));
+        assert_eq!(Ruby::api_version("jruby-9.4.5.0"), None);
+    }
+}
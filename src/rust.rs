@@ -0,0 +1,247 @@
+use crate::paths;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Toolchain files rustup reads, in order of preference
+const TOOLCHAIN_FILES: &[&str] = &["rust-toolchain.toml", "rust-toolchain"];
+
+/// An installed toolchain picked for the project
+#[derive(Debug)]
+pub struct Toolchain {
+    /// Value for RUSTUP_TOOLCHAIN
+    pub name: String,
+    /// Toolchain directory, containing bin/cargo, bin/rustc, ...
+    pub dir: PathBuf,
+    /// Where the toolchain name came from
+    pub origin: String,
+}
+
+#[derive(Deserialize)]
+struct ToolchainFile {
+    toolchain: ToolchainSection,
+}
+
+#[derive(Deserialize)]
+struct ToolchainSection {
+    channel: Option<String>,
+    /// Custom toolchain directory, relative to the toolchain file
+    path: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct RustupSettings {
+    default_host_triple: Option<String>,
+}
+
+pub struct Rust;
+
+impl Rust {
+    /// Find the toolchain for `use_rust [toolchain]`. Without a name the
+    /// nearest rust-toolchain.toml or rust-toolchain decides.
+    pub fn resolve(toolchain: Option<&str>, working_dir: &Path) -> Result<Toolchain> {
+        let rustup_home = Self::rustup_home()?;
+
+        let (name, origin) = match toolchain {
+            Some(name) => (name.to_string(), "use_rust".to_string()),
+            None => {
+                let (file, section) = Self::toolchain_file(working_dir)?;
+                let origin = file.display().to_string();
+
+                if let Some(path) = section.path {
+                    let dir = file.parent().unwrap_or(working_dir).join(&path);
+                    if !dir.join("bin").is_dir() {
+                        anyhow::bail!("Rust toolchain path {} (from {}) has no bin directory", dir.display(), origin);
+                    }
+                    // The toolchain file isn't part of what the user approved, so it
+                    // may not point PATH at directories the project itself controls
+                    if !path.is_absolute() || paths::canonical(&dir).starts_with(paths::canonical(working_dir)) {
+                        anyhow::bail!(
+                            "Rust toolchain path {} (from {}) must be absolute and outside the project",
+                            path.display(),
+                            origin
+                        );
+                    }
+                    let name = dir.display().to_string();
+                    return Ok(Toolchain { name, dir, origin });
+                }
+
+                let channel = section
+                    .channel
+                    .with_context(|| format!("{} sets neither channel nor path", origin))?;
+                (channel, origin)
+            }
+        };
+
+        // A name is a directory below toolchains, so it can't be allowed to leave it
+        if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+            anyhow::bail!("Invalid Rust toolchain name '{}' (from {})", name, origin);
+        }
+
+        let dir = Self::toolchain_dir(&rustup_home, &name)
+            .with_context(|| format!("Cannot use Rust toolchain '{}' (from {})", name, origin))?;
+        Ok(Toolchain { name, dir, origin })
+    }
+
+    /// The directory a toolchain file's `path` points to, as written and
+    /// resolved, when `use_rust` has no toolchain name
+    pub fn local_path(toolchain: Option<&str>, working_dir: &Path) -> Option<(String, PathBuf)> {
+        if toolchain.is_some() {
+            return None;
+        }
+        let (file, section) = Self::toolchain_file(working_dir).ok()?;
+        let path = section.path?;
+        let dir = file.parent().unwrap_or(working_dir).join(&path);
+        Some((path.display().to_string(), dir))
+    }
+
+    /// Search the env file's directory and its parents for a toolchain file
+    fn toolchain_file(working_dir: &Path) -> Result<(PathBuf, ToolchainSection)> {
+        for dir in working_dir.ancestors() {
+            for name in TOOLCHAIN_FILES {
+                let file = dir.join(name);
+                let Ok(contents) = fs::read_to_string(&file) else {
+                    continue;
+                };
+                let section = Self::parse_toolchain_file(&contents)
+                    .with_context(|| format!("Failed to parse {}", file.display()))?;
+                return Ok((file, section));
+            }
+        }
+
+        anyhow::bail!(
+            "use_rust needs a toolchain: none given and no {} in {} or its parents",
+            TOOLCHAIN_FILES.join(" or "),
+            working_dir.display()
+        )
+    }
+
+    /// Parse rust-toolchain.toml, or a legacy rust-toolchain file holding just the channel
+    fn parse_toolchain_file(contents: &str) -> Result<ToolchainSection> {
+        if let Ok(file) = toml::from_str::<ToolchainFile>(contents) {
+            return Ok(file.toolchain);
+        }
+
+        let channel = contents
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .filter(|line| !line.contains(['=', '[', ' ']))
+            .context("Expected a [toolchain] table or a bare channel name")?;
+
+        Ok(ToolchainSection {
+            channel: Some(channel.to_string()),
+            path: None,
+        })
+    }
+
+    /// Installed toolchain directory, named either as given or with the host triple appended
+    fn toolchain_dir(rustup_home: &Path, name: &str) -> Result<PathBuf> {
+        let toolchains = rustup_home.join("toolchains");
+
+        let exact = toolchains.join(name);
+        if exact.join("bin").is_dir() {
+            return Ok(exact);
+        }
+
+        let host = Self::host_triple(rustup_home);
+        let with_host = toolchains.join(format!("{}-{}", name, host));
+        if with_host.join("bin").is_dir() {
+            return Ok(with_host);
+        }
+
+        let mut installed: Vec<String> = fs::read_dir(&toolchains)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        installed.sort();
+
+        anyhow::bail!(
+            "not installed (looked for {}). Run 'rustup toolchain install {}'. Installed: {}",
+            with_host.display(),
+            name,
+            if installed.is_empty() { "none".to_string() } else { installed.join(", ") }
+        )
+    }
+
+    fn rustup_home() -> Result<PathBuf> {
+        if let Some(home) = env::var_os("RUSTUP_HOME") {
+            return Ok(PathBuf::from(home));
+        }
+        Ok(dirs::home_dir()
+            .context("Failed to determine home directory")?
+            .join(".rustup"))
+    }
+
+    /// Host triple rustup was set up with, or the one this binary was built for
+    fn host_triple(rustup_home: &Path) -> String {
+        fs::read_to_string(rustup_home.join("settings.toml"))
+            .ok()
+            .and_then(|contents| toml::from_str::<RustupSettings>(&contents).ok())
+            .and_then(|settings| settings.default_host_triple)
+            .unwrap_or_else(|| {
+                let os = match env::consts::OS {
+                    "linux" => "unknown-linux-gnu",
+                    "macos" => "apple-darwin",
+                    other => other,
+                };
+                format!("{}-{}", env::consts::ARCH, os)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_toolchain_files() {
+        let toml = "[toolchain]\nchannel = \"1.75.0\"\ncomponents = [\"clippy\"]\n";
+        assert_eq!(Rust::parse_toolchain_file(toml).unwrap().channel.as_deref(), Some("1.75.0"));
+        assert_eq!(Rust::parse_toolchain_file("nightly-2024-01-01\n").unwrap().channel.as_deref(), Some("nightly-2024-01-01"));
+        assert!(Rust::parse_toolchain_file("[package]\nname = \"x\"\n").is_err());
+    }
+
+    #[test]
+    fn test_rejects_toolchains_from_inside_the_project() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        let outside = dir.path().join("toolchain");
+        fs::create_dir_all(project.join("tc/bin")).unwrap();
+        fs::create_dir_all(outside.join("bin")).unwrap();
+
+        let resolve_with = |contents: &str| {
+            fs::write(project.join("rust-toolchain.toml"), contents).unwrap();
+            Rust::resolve(None, &project)
+        };
+
+        assert!(resolve_with("[toolchain]\npath = \"tc\"\n").is_err());
+        let inside = format!("[toolchain]\npath = \"{}\"\n", project.join("tc").display());
+        assert!(format!("{:#}", resolve_with(&inside).unwrap_err()).contains("outside the project"));
+        let toolchain = resolve_with(&format!("[toolchain]\npath = \"{}\"\n", outside.display())).unwrap();
+        assert_eq!(toolchain.dir, outside);
+
+        let error = format!("{:#}", resolve_with("[toolchain]\nchannel = \"../../evil\"\n").unwrap_err());
+        assert!(error.contains("Invalid Rust toolchain name"), "{}", error);
+        assert!(Rust::resolve(Some(".."), &project).is_err());
+    }
+
+    #[test]
+    fn test_toolchain_dir_appends_host() {
+        let home = tempfile::tempdir().unwrap();
+        fs::write(home.path().join("settings.toml"), "default_host_triple = \"riscv64gc-unknown-linux-gnu\"\n").unwrap();
+        let installed = home.path().join("toolchains/1.75.0-riscv64gc-unknown-linux-gnu");
+        fs::create_dir_all(installed.join("bin")).unwrap();
+
+        assert_eq!(Rust::toolchain_dir(home.path(), "1.75.0").unwrap(), installed);
+
+        let error = format!("{:#}", Rust::toolchain_dir(home.path(), "1.80.0").unwrap_err());
+        assert!(error.contains("rustup toolchain install 1.80.0"));
+        assert!(error.contains("Installed: 1.75.0-riscv64gc-unknown-linux-gnu"));
+    }
+}
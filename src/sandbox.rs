@@ -0,0 +1,278 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command as ProcessCommand, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Rules applied to sandboxed commands, as shown to the user when something fails
+pub const FILESYSTEM_RULE: &str = "filesystem is read-only outside the project (Landlock)";
+pub const NETWORK_RULE: &str = "network and local sockets are disabled (Landlock TCP rules, seccomp on socket())";
+
+pub struct Sandbox;
+
+impl Sandbox {
+    /// Run a shell command with write access limited to `project` and no
+    /// sockets, in the environment `env`, returning its stdout. The command
+    /// and everything it started are killed once `timeout` has passed.
+    #[cfg(target_os = "linux")]
+    pub fn run(command: &str, project: &Path, env: &BTreeMap<String, String>, timeout: Duration) -> Result<String> {
+        use std::os::unix::process::CommandExt;
+
+        let network_filter = linux::network_filter()?;
+        // Everything that allocates or opens files happens here, before the fork
+        let mut ruleset = Some(linux::filesystem_ruleset(project)?);
+
+        let mut process = ProcessCommand::new("/bin/sh");
+        process
+            .arg("-c")
+            .arg(command)
+            .current_dir(project)
+            .env_clear()
+            .envs(env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            // Its own process group, so a timeout can kill whatever it spawned too
+            .process_group(0);
+
+        // The restrictions are applied in the child between fork and exec, so
+        // durrrrrenv itself stays unrestricted
+        unsafe {
+            process.pre_exec(move || {
+                let ruleset = ruleset
+                    .take()
+                    .ok_or_else(|| std::io::Error::other("Sandbox ruleset was already used"))?;
+                linux::restrict_self(ruleset)?;
+                seccompiler::apply_filter(&network_filter).map_err(std::io::Error::other)?;
+                Ok(())
+            });
+        }
+
+        let mut child = process
+            .spawn()
+            .with_context(|| format!("Failed to start sandboxed command `{}`", command))?;
+
+        // Read both pipes while waiting so a chatty command can't fill one and stall
+        let stdout = Self::read_in_background(child.stdout.take());
+        let stderr = Self::read_in_background(child.stderr.take());
+
+        let deadline = Instant::now() + timeout;
+        let timed_out = || {
+            anyhow::anyhow!(
+                "Sandboxed command `{}` was killed after {} seconds (sandbox.timeout_secs)",
+                command,
+                timeout.as_secs()
+            )
+        };
+        let group = child.id() as i32;
+        let kill_group = || unsafe {
+            libc::kill(-group, libc::SIGKILL);
+        };
+
+        let status = loop {
+            if let Some(status) = child.try_wait().context("Failed to wait for sandboxed command")? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                kill_group();
+                let _ = child.wait();
+                return Err(timed_out());
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+
+        // Whatever it left running in the background would otherwise keep the pipes open
+        kill_group();
+
+        // A process that left the group can still hold a pipe, so the reads share the deadline
+        let remaining = || deadline.saturating_duration_since(Instant::now());
+        let stdout = stdout.recv_timeout(remaining()).map_err(|_| timed_out())?;
+        let stderr = stderr.recv_timeout(remaining()).map_err(|_| timed_out())?;
+        let stderr = String::from_utf8_lossy(&stderr).into_owned();
+
+        if !status.success() {
+            anyhow::bail!(
+                "Sandboxed command `{}` failed ({}){}\n{}",
+                command,
+                status,
+                Self::format_stderr(&stderr),
+                Self::blocking_rule(&stderr)
+            );
+        }
+
+        String::from_utf8(stdout)
+            .with_context(|| format!("Sandboxed command `{}` produced non-UTF-8 output", command))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn read_in_background(pipe: Option<impl Read + Send + 'static>) -> mpsc::Receiver<Vec<u8>> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buffer);
+            }
+            let _ = sender.send(buffer);
+        });
+        receiver
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn run(
+        command: &str,
+        _project: &Path,
+        _env: &BTreeMap<String, String>,
+        _timeout: Duration,
+    ) -> Result<String> {
+        anyhow::bail!(
+            "Cannot sandbox `{}`: sandboxing requires Linux (Landlock and seccomp)",
+            command
+        )
+    }
+
+    fn format_stderr(stderr: &str) -> String {
+        let stderr = stderr.trim();
+        if stderr.is_empty() {
+            String::new()
+        } else {
+            format!(":\n  {}", stderr.replace('\n', "\n  "))
+        }
+    }
+
+    /// Best guess at which sandbox rule a failure ran into, from its error output
+    fn blocking_rule(stderr: &str) -> String {
+        let lower = stderr.to_lowercase();
+        let network_hint = ["network", "socket", "connect", "resolve", "host", "dns"]
+            .iter()
+            .any(|hint| lower.contains(hint));
+        let denied_hint = ["permission denied", "operation not permitted", "read-only file system"]
+            .iter()
+            .any(|hint| lower.contains(hint));
+
+        if network_hint {
+            format!("Likely blocked by sandbox rule: {}", NETWORK_RULE)
+        } else if denied_hint {
+            format!("Likely blocked by sandbox rule: {}", FILESYSTEM_RULE)
+        } else {
+            format!("Sandbox rules in effect: {}; {}", FILESYSTEM_RULE, NETWORK_RULE)
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use anyhow::{Context, Result};
+    use landlock::{
+        path_beneath_rules, Access, AccessFs, AccessNet, CompatLevel, Compatible, Ruleset,
+        RulesetAttr, RulesetCreated, RulesetCreatedAttr, RulesetStatus, ABI,
+    };
+    use seccompiler::{BpfProgram, SeccompAction, SeccompFilter};
+    use std::collections::BTreeMap;
+    use std::io;
+    use std::path::Path;
+
+    const ABI_VERSION: ABI = ABI::V4;
+
+    /// Allow reading everywhere, writing only below the project and to
+    /// /dev/null and /dev/tty, and no TCP connections
+    pub fn filesystem_ruleset(project: &Path) -> Result<RulesetCreated> {
+        Ruleset::default()
+            .handle_access(AccessFs::from_all(ABI_VERSION))?
+            // Network rules need a newer kernel; seccomp covers them when missing
+            .set_compatibility(CompatLevel::BestEffort)
+            .handle_access(AccessNet::from_all(ABI_VERSION))?
+            .create()?
+            .add_rules(path_beneath_rules(["/"], AccessFs::from_read(ABI_VERSION)))?
+            .add_rules(path_beneath_rules(
+                [project, Path::new("/dev/null"), Path::new("/dev/tty")],
+                AccessFs::from_all(ABI_VERSION),
+            ))
+            .context("Failed to build the Landlock ruleset")
+    }
+
+    /// Enforce a ruleset on the calling process; runs in the forked child
+    pub fn restrict_self(ruleset: RulesetCreated) -> io::Result<()> {
+        let status = ruleset
+            .restrict_self()
+            .map_err(|e| io::Error::other(e.to_string()))?;
+
+        if status.ruleset == RulesetStatus::NotEnforced {
+            return Err(io::Error::other(
+                "Landlock is not supported by this kernel, refusing to run unsandboxed",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Seccomp filter failing socket creation of any address family with
+    /// EPERM, so Unix sockets (e.g. a Docker or SSH agent socket) and netlink
+    /// are out of reach too. socketpair() stays allowed.
+    pub fn network_filter() -> Result<BpfProgram> {
+        let mut rules = BTreeMap::new();
+        // No conditions: every socket() call matches
+        rules.insert(libc::SYS_socket, Vec::new());
+
+        let filter = SeccompFilter::new(
+            rules,
+            SeccompAction::Allow,
+            SeccompAction::Errno(libc::EPERM as u32),
+            std::env::consts::ARCH
+                .try_into()
+                .context("Seccomp is not supported on this architecture")?,
+        )?;
+
+        Ok(filter.try_into()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocking_rule_guesses() {
+        assert!(Sandbox::blocking_rule("curl: (7) Couldn't connect to server").contains(NETWORK_RULE));
+        assert!(Sandbox::blocking_rule("touch: /etc/x: Permission denied").contains(FILESYSTEM_RULE));
+        assert!(Sandbox::blocking_rule("").starts_with("Sandbox rules in effect"));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_timeout_kills_command_and_children() {
+        let dir = tempfile::tempdir().unwrap();
+        let started = Instant::now();
+
+        let result = Sandbox::run("sleep 30 & sleep 30", dir.path(), &test_env(), Duration::from_millis(200));
+
+        let error = format!("{:#}", result.unwrap_err());
+        // Kernels without Landlock refuse to start the command at all
+        assert!(error.contains("killed after") || error.contains("Landlock"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_background_jobs_do_not_hold_up_the_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let started = Instant::now();
+
+        let result = Sandbox::run("sleep 30 & echo \"$GREETING\"", dir.path(), &test_env(), Duration::from_secs(20));
+
+        match result {
+            Ok(output) => assert_eq!(output, "hello\n"),
+            Err(e) => assert!(format!("{:#}", e).contains("Landlock"), "{:#}", e),
+        }
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    fn test_env() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("PATH".to_string(), "/usr/bin:/bin".to_string()),
+            ("GREETING".to_string(), "hello".to_string()),
+        ])
+    }
+}
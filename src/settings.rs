@@ -0,0 +1,155 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// User settings read from `~/.config/durrrrrenv/config.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub permissions: PermissionSettings,
+    pub signing: SigningSettings,
+    pub sandbox: SandboxSettings,
+    pub pinning: PinningSettings,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PermissionSettings {
+    /// Directories shared with a group: files below these may be group-writable
+    /// or owned by other users
+    pub shared_dirs: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SigningSettings {
+    /// Public keys (base64) whose signatures are trusted, by name
+    pub trusted_keys: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SandboxSettings {
+    /// Run `source <(...)` commands in a Landlock/seccomp sandbox instead of the shell
+    pub process_substitution: bool,
+    /// Seconds a sandboxed command may run before it is killed
+    pub timeout_secs: u64,
+}
+
+impl Default for SandboxSettings {
+    fn default() -> Self {
+        Self {
+            process_substitution: false,
+            timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PinningSettings {
+    /// Also record a hash of each pinned binary
+    pub hash: bool,
+    /// Refuse to load instead of warning when a pinned binary changed
+    pub refuse_on_mismatch: bool,
+}
+
+impl Settings {
+    /// Get the path to the settings file
+    pub fn settings_path() -> Result<PathBuf> {
+        #[cfg(test)]
+        let config_dir = crate::paths::test_dir().join("durrrrrenv");
+        #[cfg(not(test))]
+        let config_dir = dirs::config_dir()
+            .context("Failed to determine config directory")?
+            .join("durrrrrenv");
+
+        Ok(config_dir.join("config.toml"))
+    }
+
+    /// Load settings from disk, falling back to defaults if the file doesn't exist
+    pub fn load() -> Result<Self> {
+        let settings_path = Self::settings_path()?;
+
+        if !settings_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = fs::read_to_string(&settings_path)
+            .context("Failed to read settings file")?;
+
+        Self::from_toml(&contents)
+            .with_context(|| format!("Failed to parse {}", settings_path.display()))
+    }
+
+    /// Parse settings from TOML text
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        Ok(toml::from_str(contents)?)
+    }
+}
+
+impl PermissionSettings {
+    /// Check whether a path lies inside one of the configured shared directories
+    pub fn is_shared(&self, path: &Path) -> bool {
+        self.shared_dirs.iter().any(|dir| {
+            let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.clone());
+            path.starts_with(dir)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let settings = Settings::from_toml("").unwrap();
+        assert!(settings.permissions.shared_dirs.is_empty());
+        assert!(settings.signing.trusted_keys.is_empty());
+        assert!(!settings.sandbox.process_substitution);
+        assert_eq!(settings.sandbox.timeout_secs, 30);
+        assert!(!settings.pinning.hash);
+        assert!(!settings.pinning.refuse_on_mismatch);
+
+        let dir = tempfile::tempdir().unwrap();
+        crate::paths::use_test_dir(dir.path());
+        assert_eq!(Settings::load().unwrap().sandbox.timeout_secs, 30);
+    }
+
+    #[test]
+    fn test_parse_settings() {
+        let settings = Settings::from_toml(
+            r#"
+            [permissions]
+            shared_dirs = ["/srv/projects"]
+
+            [sandbox]
+            process_substitution = true
+
+            [pinning]
+            hash = true
+
+            [signing.trusted_keys]
+            platform-team = "ak7jfeClPhBM4a2DRsCnhG+P6o5JqUoyLlbJwD9zdsU="
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(settings.permissions.shared_dirs, vec![PathBuf::from("/srv/projects")]);
+        assert!(settings.sandbox.process_substitution);
+        // Keys left out of a table keep their defaults
+        assert_eq!(settings.sandbox.timeout_secs, 30);
+        assert!(settings.pinning.hash);
+        assert!(!settings.pinning.refuse_on_mismatch);
+        assert!(settings.signing.trusted_keys.contains_key("platform-team"));
+    }
+
+    #[test]
+    fn test_rejects_wrong_types() {
+        assert!(Settings::from_toml("[sandbox]\ntimeout_secs = \"soon\"\n").is_err());
+        assert!(Settings::from_toml("[permissions]\nshared_dirs = \"/srv\"\n").is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use crate::settings::SigningSettings;
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+/// Prefix mixed into every signed message so signatures can't be reused elsewhere
+const SIGNATURE_CONTEXT: &[u8] = b"durrrrrenv .local_environment v1\n";
+
+/// Detached signature stored next to the env file
+pub struct FileSignature {
+    pub public_key: VerifyingKey,
+    pub signature: Signature,
+}
+
+impl FileSignature {
+    /// Path of the signature file for an env file
+    pub fn path_for(env_file: &Path) -> PathBuf {
+        let mut name = env_file.as_os_str().to_os_string();
+        name.push(".sig");
+        PathBuf::from(name)
+    }
+
+    /// Sign env file content
+    pub fn sign(key: &SigningKey, content: &str) -> Self {
+        Self {
+            public_key: key.verifying_key(),
+            signature: key.sign(&Self::message(content)),
+        }
+    }
+
+    /// Parse a signature file
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut public_key = None;
+        let mut signature = None;
+
+        for line in text.lines() {
+            if let Some(value) = line.strip_prefix("key: ") {
+                public_key = Some(decode_public_key(value)?);
+            } else if let Some(value) = line.strip_prefix("signature: ") {
+                let bytes: [u8; 64] = BASE64
+                    .decode(value.trim())
+                    .context("Signature is not valid base64")?
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("Signature must be 64 bytes"))?;
+                signature = Some(Signature::from_bytes(&bytes));
+            }
+        }
+
+        Ok(Self {
+            public_key: public_key.context("Signature file has no key line")?,
+            signature: signature.context("Signature file has no signature line")?,
+        })
+    }
+
+    /// Render the signature file
+    pub fn to_text(&self) -> String {
+        format!(
+            "key: {}\nsignature: {}\n",
+            BASE64.encode(self.public_key.as_bytes()),
+            BASE64.encode(self.signature.to_bytes())
+        )
+    }
+
+    /// Check the signature against the content and the keyring.
+    /// Returns the name of the trusted key that made it.
+    pub fn verify(&self, content: &str, signing: &SigningSettings) -> Result<String> {
+        let name = signing
+            .trusted_keys
+            .iter()
+            .find(|(_, key)| {
+                decode_public_key(key)
+                    .map(|key| key == self.public_key)
+                    .unwrap_or(false)
+            })
+            .map(|(name, _)| name.clone())
+            .with_context(|| {
+                format!(
+                    "signed by untrusted key {}",
+                    BASE64.encode(self.public_key.as_bytes())
+                )
+            })?;
+
+        self.public_key
+            .verify(&Self::message(content), &self.signature)
+            .context("signature does not match the file content")?;
+
+        Ok(name)
+    }
+
+    fn message(content: &str) -> Vec<u8> {
+        let mut message = SIGNATURE_CONTEXT.to_vec();
+        message.extend_from_slice(content.as_bytes());
+        message
+    }
+}
+
+/// Verify the signature next to an env file, if there is one.
+/// Returns Ok(None) when the file isn't signed.
+pub fn verify_env_file(env_file: &Path, content: &str, signing: &SigningSettings) -> Result<Option<String>> {
+    let sig_path = FileSignature::path_for(env_file);
+
+    let text = match fs::read_to_string(&sig_path) {
+        Ok(text) => text,
+        Err(_) => return Ok(None),
+    };
+
+    let signature = FileSignature::parse(&text)
+        .with_context(|| format!("Invalid signature file {}", sig_path.display()))?;
+
+    signature.verify(content, signing).map(Some)
+}
+
+/// Default location of the user's signing key
+pub fn default_key_path() -> Result<PathBuf> {
+    Ok(dirs::config_dir()
+        .context("Failed to determine config directory")?
+        .join("durrrrrenv")
+        .join("signing.key"))
+}
+
+/// Generate a new signing key and write it with owner-only permissions
+pub fn generate_key(path: &Path) -> Result<SigningKey> {
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).context("Failed to gather randomness")?;
+    let key = SigningKey::from_bytes(&seed);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("Failed to create key directory")?;
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    writeln!(file, "{}", BASE64.encode(key.to_bytes()))?;
+
+    Ok(key)
+}
+
+/// Read a signing key written by `generate_key`
+pub fn load_key(path: &Path) -> Result<SigningKey> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read signing key {}", path.display()))?;
+    let seed: [u8; 32] = BASE64
+        .decode(text.trim())
+        .context("Signing key is not valid base64")?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Signing key must be 32 bytes"))?;
+
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// Base64 form of a public key, as used in the keyring
+pub fn encode_public_key(key: &VerifyingKey) -> String {
+    BASE64.encode(key.as_bytes())
+}
+
+fn decode_public_key(text: &str) -> Result<VerifyingKey> {
+    let bytes: [u8; 32] = BASE64
+        .decode(text.trim())
+        .context("Public key is not valid base64")?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Public key must be 32 bytes"))?;
+
+    VerifyingKey::from_bytes(&bytes).context("Invalid public key")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyring(name: &str, key: &SigningKey) -> SigningSettings {
+        let mut settings = SigningSettings::default();
+        settings
+            .trusted_keys
+            .insert(name.to_string(), encode_public_key(&key.verifying_key()));
+        settings
+    }
+
+    #[test]
+    fn test_sign_and_verify_roundtrip() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let content = "python_venv .venv\n";
+
+        let text = FileSignature::sign(&key, content).to_text();
+        let parsed = FileSignature::parse(&text).unwrap();
+
+        assert_eq!(parsed.verify(content, &keyring("team", &key)).unwrap(), "team");
+    }
+
+    #[test]
+    fn test_rejects_modified_content() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let signature = FileSignature::sign(&key, "python_venv .venv\n");
+
+        assert!(signature
+            .verify("source ~/evil.sh\n", &keyring("team", &key))
+            .is_err());
+    }
+
+    #[test]
+    fn test_rejects_untrusted_key() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let other = SigningKey::from_bytes(&[9u8; 32]);
+        let signature = FileSignature::sign(&key, "python_venv\n");
+
+        let err = signature
+            .verify("python_venv\n", &keyring("team", &other))
+            .unwrap_err();
+        assert!(err.to_string().contains("untrusted key"));
+    }
+}
@@ -0,0 +1,306 @@
+use crate::paths;
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where a tool keeps its executables inside an install directory
+const BIN_DIRS: &[&str] = &["bin", "go/bin"];
+
+/// asdf plugin names that mise installs under another name
+const ALIASES: &[(&str, &str)] = &[("nodejs", "node"), ("golang", "go")];
+
+/// A tool from .tool-versions found installed
+#[derive(Debug)]
+pub struct Tool {
+    pub name: String,
+    /// Installed version that was picked
+    pub version: String,
+    pub bin: PathBuf,
+    /// "asdf", "mise" or "path"
+    pub manager: &'static str,
+}
+
+/// What a .tool-versions file resolves to
+#[derive(Debug)]
+pub struct Resolution {
+    pub file: PathBuf,
+    pub tools: Vec<Tool>,
+    /// Tools none of whose listed versions are installed, as written ("nodejs 20.11.0")
+    pub missing: Vec<String>,
+    /// Versions that were not considered, with the reason
+    pub ignored: Vec<String>,
+}
+
+pub struct ToolVersions;
+
+impl ToolVersions {
+    /// Resolve each line of .tool-versions (or the given file) to an installed
+    /// version from asdf or mise. Tools that aren't installed are reported,
+    /// not treated as errors.
+    pub fn resolve(file: Option<&str>, working_dir: &Path) -> Result<Resolution> {
+        let file = Self::file(file, working_dir);
+        let file_dir = file.parent().unwrap_or(working_dir);
+        let contents = fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+
+        let roots = Self::install_roots();
+        let mut tools = Vec::new();
+        let mut missing = Vec::new();
+        let mut ignored = Vec::new();
+
+        for (name, versions) in Self::parse(&contents) {
+            if !Self::is_tool_name(name) {
+                ignored.push(format!("{}: tool names may not contain '/' or '..'", name));
+                continue;
+            }
+
+            let found = versions.iter().find_map(|version| {
+                // .tool-versions isn't part of what the user approved, so it may not
+                // point PATH at directories the project itself controls
+                if let Some(path) = Self::local_path(version, file_dir) {
+                    if !Path::new(&version["path:".len()..]).is_absolute()
+                        || paths::canonical(&path).starts_with(paths::canonical(working_dir))
+                    {
+                        ignored.push(format!(
+                            "{} {}: path: versions must be absolute and outside the project",
+                            name, version
+                        ));
+                        return None;
+                    }
+                }
+                Self::find(&roots, file_dir, name, version)
+            });
+
+            match found {
+                Some(tool) => tools.push(tool),
+                // `system` means whatever is on PATH already
+                None if versions.contains(&"system") => {}
+                None => missing.push(format!("{} {}", name, versions.join(" "))),
+            }
+        }
+
+        Ok(Resolution { file, tools, missing, ignored })
+    }
+
+    /// The file a `use_tool_versions` line reads
+    pub fn file(file: Option<&str>, working_dir: &Path) -> PathBuf {
+        working_dir.join(file.unwrap_or(".tool-versions"))
+    }
+
+    /// Directories named by `path:` versions in the file, as written and resolved
+    pub fn local_paths(file: &Path) -> Vec<(String, PathBuf)> {
+        let contents = fs::read_to_string(file).unwrap_or_default();
+        let file_dir = file.parent().unwrap_or(Path::new("/"));
+
+        Self::parse(&contents)
+            .into_iter()
+            .flat_map(|(_, versions)| versions)
+            .filter_map(|version| Some((version.to_string(), Self::local_path(version, file_dir)?)))
+            .collect()
+    }
+
+    /// Whether a tool name stays a single directory below the install roots
+    fn is_tool_name(name: &str) -> bool {
+        !name.is_empty() && !name.contains('/') && !name.contains("..") && name != "."
+    }
+
+    /// Where a `path:` version points; relative ones are relative to the file
+    fn local_path(version: &str, file_dir: &Path) -> Option<PathBuf> {
+        version.strip_prefix("path:").map(|path| file_dir.join(path))
+    }
+
+    /// Tool names with their versions, in order of preference
+    fn parse(contents: &str) -> Vec<(&str, Vec<&str>)> {
+        contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .filter_map(|line| {
+                let mut words = line.split_whitespace();
+                let name = words.next()?;
+                let versions: Vec<&str> = words.collect();
+                (!versions.is_empty()).then_some((name, versions))
+            })
+            .collect()
+    }
+
+    /// asdf's and mise's installs directories
+    fn install_roots() -> Vec<(&'static str, PathBuf)> {
+        let home = dirs::home_dir().unwrap_or_default();
+        let root = |var: &str, default: PathBuf| env::var_os(var).map(PathBuf::from).unwrap_or(default);
+
+        vec![
+            ("asdf", root("ASDF_DATA_DIR", home.join(".asdf")).join("installs")),
+            (
+                "mise",
+                root("MISE_DATA_DIR", dirs::data_dir().unwrap_or_default().join("mise")).join("installs"),
+            ),
+        ]
+    }
+
+    /// An installed version matching `version`: exactly, or the newest one
+    /// it is a prefix of (`20` for 20.11.1, also written `latest:20` or
+    /// `prefix:20`), or the newest for `latest`. `ref:<ref>` matches the
+    /// `ref-<ref>` directory both managers install git refs to.
+    fn find(roots: &[(&'static str, PathBuf)], file_dir: &Path, name: &str, version: &str) -> Option<Tool> {
+        // The name becomes a directory below each install root
+        if !Self::is_tool_name(name) {
+            return None;
+        }
+
+        if let Some(path) = Self::local_path(version, file_dir) {
+            return Self::bin_dir(&path).map(|bin| Tool {
+                name: name.to_string(),
+                version: version.to_string(),
+                bin,
+                manager: "path",
+            });
+        }
+
+        let (exact, prefix) = match version.split_once(':') {
+            Some(("ref", git_ref)) => (format!("ref-{}", git_ref), None),
+            Some(("latest" | "prefix", prefix)) => (String::new(), Some(prefix)),
+            // Other prefixed forms (sub-1:, system:) aren't supported and stay missing
+            Some(_) => return None,
+            None => (version.to_string(), Some(version)),
+        };
+
+        roots.iter().find_map(|(manager, installs)| {
+            let tool_dir = Self::install_names(name)
+                .map(|name| installs.join(name))
+                .find(|tool_dir| tool_dir.is_dir())?;
+            let installed: Vec<String> = fs::read_dir(&tool_dir)
+                .ok()?
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .filter(|installed| Self::bin_dir(&tool_dir.join(installed)).is_some())
+                .collect();
+
+            let picked = if installed.contains(&exact) {
+                exact.clone()
+            } else {
+                let prefix = prefix?;
+                let wanted = Self::components(prefix);
+                installed
+                    .into_iter()
+                    .filter(|installed| {
+                        let components = Self::components(installed);
+                        prefix == "latest" || (!wanted.is_empty() && components.starts_with(&wanted))
+                    })
+                    .max_by_key(|installed| Self::components(installed))?
+            };
+
+            Some(Tool {
+                name: name.to_string(),
+                bin: Self::bin_dir(&tool_dir.join(&picked))?,
+                version: picked,
+                manager,
+            })
+        })
+    }
+
+    /// The tool's name and the name the other manager uses for it
+    fn install_names(name: &str) -> impl Iterator<Item = &str> {
+        let alias = ALIASES.iter().find_map(|&(asdf, mise)| match name {
+            _ if name == asdf => Some(mise),
+            _ if name == mise => Some(asdf),
+            _ => None,
+        });
+        std::iter::once(name).chain(alias)
+    }
+
+    fn bin_dir(install: &Path) -> Option<PathBuf> {
+        BIN_DIRS.iter().map(|bin| install.join(bin)).find(|bin| bin.is_dir())
+    }
+
+    fn components(version: &str) -> Vec<u64> {
+        version
+            .trim_start_matches('v')
+            .split('.')
+            .map_while(|part| part.parse().ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tool_versions() {
+        let parsed = ToolVersions::parse("# tools\nnodejs 20.11.0 18.19.0\npython 3.11.7 # pinned\n\nruby\n");
+        assert_eq!(
+            parsed,
+            vec![("nodejs", vec!["20.11.0", "18.19.0"]), ("python", vec!["3.11.7"])]
+        );
+    }
+
+    #[test]
+    fn test_find_installed_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let asdf = dir.path().join("asdf");
+        let mise = dir.path().join("mise");
+        fs::create_dir_all(asdf.join("golang/1.21.5/go/bin")).unwrap();
+        fs::create_dir_all(mise.join("node/20.9.0/bin")).unwrap();
+        fs::create_dir_all(mise.join("node/20.11.1/bin")).unwrap();
+        fs::create_dir_all(mise.join("node/21.0.0-broken")).unwrap();
+        let roots = vec![("asdf", asdf.clone()), ("mise", mise.clone())];
+
+        let go = ToolVersions::find(&roots, dir.path(), "golang", "1.21.5").unwrap();
+        assert_eq!((go.bin, go.manager), (asdf.join("golang/1.21.5/go/bin"), "asdf"));
+
+        let node = ToolVersions::find(&roots, dir.path(), "node", "20").unwrap();
+        assert_eq!(node.version, "20.11.1");
+        assert_eq!(ToolVersions::find(&roots, dir.path(), "node", "latest").unwrap().version, "20.11.1");
+        assert!(ToolVersions::find(&roots, dir.path(), "node", "22").is_none());
+
+        // asdf's plugin names find mise's installs and the other way around
+        assert_eq!(ToolVersions::find(&roots, dir.path(), "nodejs", "latest:20.9").unwrap().version, "20.9.0");
+        assert_eq!(ToolVersions::find(&roots, dir.path(), "go", "prefix:1").unwrap().manager, "asdf");
+
+        fs::create_dir_all(mise.join("node/ref-main/bin")).unwrap();
+        assert_eq!(ToolVersions::find(&roots, dir.path(), "node", "ref:main").unwrap().version, "ref-main");
+        assert!(ToolVersions::find(&roots, dir.path(), "node", "sub-1:latest").is_none());
+    }
+
+    #[test]
+    fn test_rejects_tool_names_leaving_the_install_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let installs = dir.path().join("asdf/installs");
+        fs::create_dir_all(dir.path().join("asdf/evil/1.0/bin")).unwrap();
+        fs::create_dir_all(installs.join("node/20.0.0/bin")).unwrap();
+        let roots = vec![("asdf", installs)];
+
+        assert!(ToolVersions::find(&roots, dir.path(), "../evil", "1.0").is_none());
+        assert!(ToolVersions::find(&roots, dir.path(), "..", "latest").is_none());
+        assert!(ToolVersions::find(&roots, dir.path(), "node/../node", "20").is_none());
+        assert!(ToolVersions::find(&roots, dir.path(), "node", "20").is_some());
+
+        let project = dir.path().join("app");
+        fs::create_dir_all(&project).unwrap();
+        fs::write(project.join(".tool-versions"), "../evil 1.0
+").unwrap();
+        let resolution = ToolVersions::resolve(None, &project).unwrap();
+        assert!(resolution.tools.is_empty() && resolution.missing.is_empty());
+        assert_eq!(resolution.ignored, vec!["../evil: tool names may not contain '/' or '..'"]);
+    }
+
+    #[test]
+    fn test_path_versions_must_be_outside_the_project() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("app");
+        let outside = dir.path().join("python");
+        fs::create_dir_all(project.join("bin")).unwrap();
+        fs::create_dir_all(outside.join("bin")).unwrap();
+        fs::write(
+            project.join(".tool-versions"),
+            format!("ruby path:. path:{}\npython path:{}\n", project.display(), outside.display()),
+        )
+        .unwrap();
+
+        let resolution = ToolVersions::resolve(None, &project).unwrap();
+        assert_eq!(resolution.tools.len(), 1);
+        assert_eq!(resolution.tools[0].bin, outside.join("bin"));
+        assert_eq!(resolution.ignored.len(), 2);
+        assert_eq!(resolution.missing, vec![format!("ruby path:. path:{}", project.display())]);
+    }
+}
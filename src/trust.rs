@@ -0,0 +1,248 @@
+use crate::config::Config;
+use crate::parser::Parser;
+use crate::pinning::Pinning;
+use crate::settings::Settings;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Version of the export file format
+const EXPORT_VERSION: u32 = 1;
+
+/// Portable list of approved directories
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrustExport {
+    pub version: u32,
+    pub entries: Vec<TrustEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrustEntry {
+    /// Canonical directory path on the exporting machine
+    pub path: PathBuf,
+    /// Hash of the approved .local_environment content
+    pub file_hash: String,
+    /// Directives rejected when the file was allowed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_directives: Vec<String>,
+}
+
+/// A path prefix rewrite: OLD_PREFIX=NEW_PREFIX
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathMap {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// Outcome of an import, one line per entry
+pub enum ImportResult {
+    /// Directory and the file hash it was trusted with
+    Imported(PathBuf, String),
+    Skipped(PathBuf, String),
+}
+
+impl PathMap {
+    /// Parse OLD_PREFIX=NEW_PREFIX
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (from, to) = spec
+            .split_once('=')
+            .with_context(|| format!("Invalid mapping '{}', expected OLD_PREFIX=NEW_PREFIX", spec))?;
+
+        if from.is_empty() || to.is_empty() {
+            anyhow::bail!("Invalid mapping '{}', both prefixes must be non-empty", spec);
+        }
+
+        Ok(Self {
+            from: PathBuf::from(from),
+            to: PathBuf::from(to),
+        })
+    }
+
+    /// Apply the longest matching mapping to a path
+    pub fn remap(maps: &[PathMap], path: &Path) -> PathBuf {
+        maps.iter()
+            .filter_map(|map| {
+                path.strip_prefix(&map.from)
+                    .ok()
+                    .map(|rest| {
+                        let remapped = if rest.as_os_str().is_empty() {
+                            map.to.clone()
+                        } else {
+                            map.to.join(rest)
+                        };
+                        (map.from.components().count(), remapped)
+                    })
+            })
+            .max_by_key(|(depth, _)| *depth)
+            .map(|(_, path)| path)
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+}
+
+impl TrustExport {
+    /// Collect every approved directory in the config
+    pub fn from_config(config: &Config) -> Self {
+        let mut entries: Vec<TrustEntry> = config
+            .allowed_dirs
+            .values()
+            .map(|info| TrustEntry {
+                path: info.path.clone(),
+                file_hash: info.file_hash.clone(),
+                skipped_directives: info.skipped_directives.clone(),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Self {
+            version: EXPORT_VERSION,
+            entries,
+        }
+    }
+
+    /// Read an export file
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let export: Self = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+        if export.version > EXPORT_VERSION {
+            anyhow::bail!(
+                "{} uses export format version {}, this durrrrrenv only understands up to {}",
+                path.display(),
+                export.version,
+                EXPORT_VERSION
+            );
+        }
+
+        Ok(export)
+    }
+
+    /// Merge entries into the config, skipping any whose file content no longer matches.
+    /// Binaries are pinned again on this machine, as when allowing the file.
+    pub fn import_into(&self, config: &mut Config, maps: &[PathMap], settings: &Settings) -> Result<Vec<ImportResult>> {
+        let mut results = Vec::new();
+
+        for entry in &self.entries {
+            let dir = PathMap::remap(maps, &entry.path);
+            let env_file = dir.join(".local_environment");
+
+            let content = match fs::read_to_string(&env_file) {
+                Ok(content) => content,
+                Err(e) => {
+                    results.push(ImportResult::Skipped(dir, e.to_string()));
+                    continue;
+                }
+            };
+
+            if Config::hash_content(&content) != entry.file_hash {
+                results.push(ImportResult::Skipped(dir, "file content differs".to_string()));
+                continue;
+            }
+
+            let commands = match Parser::parse(&content) {
+                Ok(commands) => commands,
+                Err(e) => {
+                    results.push(ImportResult::Skipped(dir, e.to_string()));
+                    continue;
+                }
+            };
+            let commands: Vec<_> = commands
+                .into_iter()
+                .filter(|cmd| !entry.skipped_directives.contains(&cmd.to_string()))
+                .collect();
+            let pins = Pinning::pin(&commands, &dir, settings)?;
+
+            let info = config.trust_hash(&dir, entry.file_hash.clone())?;
+            info.skipped_directives = entry.skipped_directives.clone();
+            info.pinned_binaries = pins;
+            results.push(ImportResult::Imported(dir, entry.file_hash.clone()));
+        }
+
+        config.save()?;
+
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_path_map() {
+        let map = PathMap::parse("/home/old=/Users/new").unwrap();
+        assert_eq!(map.from, PathBuf::from("/home/old"));
+        assert_eq!(map.to, PathBuf::from("/Users/new"));
+
+        assert!(PathMap::parse("/home/old").is_err());
+        assert!(PathMap::parse("=/Users/new").is_err());
+    }
+
+    #[test]
+    fn test_remap_uses_longest_prefix() {
+        let maps = vec![
+            PathMap::parse("/home/me=/Users/me").unwrap(),
+            PathMap::parse("/home/me/work=/Volumes/work").unwrap(),
+        ];
+
+        assert_eq!(
+            PathMap::remap(&maps, Path::new("/home/me/src/foo")),
+            PathBuf::from("/Users/me/src/foo")
+        );
+        assert_eq!(
+            PathMap::remap(&maps, Path::new("/home/me/work/api")),
+            PathBuf::from("/Volumes/work/api")
+        );
+        assert_eq!(
+            PathMap::remap(&maps, Path::new("/home/me")),
+            PathBuf::from("/Users/me")
+        );
+        assert_eq!(
+            PathMap::remap(&maps, Path::new("/opt/other")),
+            PathBuf::from("/opt/other")
+        );
+    }
+
+    #[test]
+    fn test_remap_matches_whole_components() {
+        let maps = vec![PathMap::parse("/home/me=/Users/me").unwrap()];
+
+        assert_eq!(
+            PathMap::remap(&maps, Path::new("/home/meg/src")),
+            PathBuf::from("/home/meg/src")
+        );
+    }
+
+    #[test]
+    fn test_import_skips_changed_and_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        crate::paths::use_test_dir(dir.path());
+        let same = dir.path().join("same");
+        let changed = dir.path().join("changed");
+        fs::create_dir_all(&same).unwrap();
+        fs::create_dir_all(&changed).unwrap();
+        fs::write(same.join(".local_environment"), "source a.sh\n").unwrap();
+        fs::write(changed.join(".local_environment"), "source evil.sh\n").unwrap();
+
+        let entry = |path: &Path| TrustEntry {
+            path: path.to_path_buf(),
+            file_hash: Config::hash_content("source a.sh\n"),
+            skipped_directives: Vec::new(),
+        };
+        let export = TrustExport {
+            version: EXPORT_VERSION,
+            entries: vec![entry(&same), entry(&changed), entry(&dir.path().join("gone"))],
+        };
+
+        let mut config = Config::default();
+        let results = export.import_into(&mut config, &[], &Settings::default()).unwrap();
+
+        assert!(matches!(&results[0], ImportResult::Imported(path, hash) if *path == same && *hash == entry(&same).file_hash));
+        assert!(matches!(&results[1], ImportResult::Skipped(path, reason) if *path == changed && reason == "file content differs"));
+        assert!(matches!(&results[2], ImportResult::Skipped(..)));
+        assert!(config.is_allowed(&same, "source a.sh\n"));
+        assert!(config.dir_info(&changed).is_none());
+    }
+}